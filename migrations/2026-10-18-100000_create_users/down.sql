@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE course_resource_links DROP COLUMN IF EXISTS Uploader_ID;
+ALTER TABLE course_resources DROP COLUMN IF EXISTS Uploader_ID;
+DROP TABLE IF EXISTS email_verification_codes;
+DROP TABLE IF EXISTS users;
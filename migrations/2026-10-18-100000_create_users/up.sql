@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE users (
+    User_ID UUID PRIMARY KEY, /* UUID: User ID */
+    Email VARCHAR NOT NULL UNIQUE, /* String: Lowercased @gju.edu.jo email address, verified with a one-time code */
+    Created_At TIMESTAMPTZ NOT NULL DEFAULT NOW() /* Date: Date the user first verified their email */
+);
+
+/* One-time codes emailed to users to verify their address, at most one pending code per email */
+CREATE TABLE email_verification_codes (
+    Email VARCHAR PRIMARY KEY, /* String: Lowercased email address the code was sent to */
+    Code_Hash VARCHAR NOT NULL, /* String: SHA-256 of the email and code, the code itself is never stored */
+    Created_At TIMESTAMPTZ NOT NULL, /* Date: When the code was sent, used to throttle resending */
+    Expires_At TIMESTAMPTZ NOT NULL, /* Date: When the code stops being accepted */
+    Attempts INT NOT NULL DEFAULT 0 /* Integer: Number of wrong guesses so far */
+);
+
+/* Existing uploads stay anonymous */
+ALTER TABLE course_resources ADD COLUMN Uploader_ID UUID REFERENCES users(User_ID);
+ALTER TABLE course_resource_links ADD COLUMN Uploader_ID UUID REFERENCES users(User_ID);
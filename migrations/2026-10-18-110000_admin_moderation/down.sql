@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS course_resources_dateuploaded_idx;
+DROP TABLE IF EXISTS admin_audit_log;
+ALTER TABLE course_resource_links DROP COLUMN IF EXISTS Date_Added;
+ALTER TABLE course_resource_links DROP COLUMN IF EXISTS Is_Hidden;
+ALTER TABLE course_resources DROP COLUMN IF EXISTS Is_Hidden;
+ALTER TABLE users DROP COLUMN IF EXISTS Is_Banned;
+ALTER TABLE users DROP COLUMN IF EXISTS Role;
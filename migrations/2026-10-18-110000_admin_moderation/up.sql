@@ -0,0 +1,23 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN Role VARCHAR NOT NULL DEFAULT 'user' CHECK (Role IN ('user', 'admin')); /* String: 'user' or 'admin' (moderators) */
+ALTER TABLE users ADD COLUMN Is_Banned BOOLEAN NOT NULL DEFAULT FALSE; /* Boolean: Banned users can't change anything */
+
+/* Hidden items are kept for moderators but not shown publicly */
+ALTER TABLE course_resources ADD COLUMN Is_Hidden BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE course_resource_links ADD COLUMN Is_Hidden BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE course_resource_links ADD COLUMN Date_Added TIMESTAMPTZ NOT NULL DEFAULT NOW(); /* Date: Date the link was added */
+
+/* Every moderation action taken through the admin API */
+CREATE TABLE admin_audit_log (
+    Audit_ID UUID PRIMARY KEY, /* UUID: Audit log entry ID */
+    Admin_ID UUID NOT NULL, /* UUID: Admin that took the action */
+    Action VARCHAR NOT NULL, /* String: What was done, such as hide_resource or ban_user */
+    Target_Type VARCHAR NOT NULL, /* String: What kind of item the action was taken on (resource, link, user, course) */
+    Target_ID VARCHAR NOT NULL, /* String: ID of the item the action was taken on */
+    Details VARCHAR, /* String: Extra information, such as the reason for a ban */
+    Created_At TIMESTAMPTZ NOT NULL DEFAULT NOW(), /* Date: When the action was taken */
+    FOREIGN KEY (Admin_ID) REFERENCES users(User_ID)
+);
+
+CREATE INDEX admin_audit_log_created_at_idx ON admin_audit_log (Created_At DESC);
+CREATE INDEX course_resources_dateuploaded_idx ON course_resources (DateUploaded DESC);
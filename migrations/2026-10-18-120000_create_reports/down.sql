@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE IF EXISTS reports;
+ALTER TABLE course_resource_files DROP COLUMN IF EXISTS Is_Hidden;
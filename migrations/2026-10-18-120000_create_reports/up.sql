@@ -0,0 +1,18 @@
+-- Your SQL goes here
+ALTER TABLE course_resource_files ADD COLUMN Is_Hidden BOOLEAN NOT NULL DEFAULT FALSE; /* Boolean: Hidden files aren't shown publicly */
+
+/* Reports of wrong or inappropriate content, an item is hidden once enough users report it */
+CREATE TABLE reports (
+    Report_ID UUID PRIMARY KEY, /* UUID: Report ID */
+    Reporter_ID UUID NOT NULL, /* UUID: User that made the report */
+    Target_Type VARCHAR NOT NULL CHECK (Target_Type IN ('resource', 'file', 'link')), /* String: What kind of item is reported */
+    Target_ID UUID NOT NULL, /* UUID: Resource, file or link ID */
+    Reason VARCHAR NOT NULL CHECK (Reason IN ('wrong_course', 'inappropriate', 'spam', 'copyright', 'broken', 'other')), /* String: Why the item was reported */
+    Details VARCHAR, /* String: Free text from the reporter */
+    Status VARCHAR NOT NULL DEFAULT 'pending' CHECK (Status IN ('pending', 'upheld', 'dismissed')), /* String: Whether a moderator reviewed the report */
+    Created_At TIMESTAMPTZ NOT NULL DEFAULT NOW(), /* Date: When the report was made */
+    FOREIGN KEY (Reporter_ID) REFERENCES users(User_ID),
+    UNIQUE (Reporter_ID, Target_Type, Target_ID)
+);
+
+CREATE INDEX reports_pending_target_idx ON reports (Target_Type, Target_ID) WHERE Status = 'pending';
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+/* A user's rating of a course, each user can rate a course once and update it later */
+CREATE TABLE course_ratings (
+    Rating_ID UUID PRIMARY KEY, /* UUID: Rating ID */
+    Course_ID VARCHAR NOT NULL, /* String: course ID, such as CS116 */
+    User_ID UUID NOT NULL, /* UUID: User that rated the course */
+    Difficulty SMALLINT NOT NULL CHECK (Difficulty BETWEEN 1 AND 5), /* Number: 1 = Easy, 5 = Very hard */
+    Workload SMALLINT NOT NULL CHECK (Workload BETWEEN 1 AND 5), /* Number: 1 = Light, 5 = Very heavy */
+    Usefulness SMALLINT NOT NULL CHECK (Usefulness BETWEEN 1 AND 5), /* Number: 1 = Useless, 5 = Very useful */
+    Review VARCHAR, /* String: Optional text review */
+    Created_At TIMESTAMPTZ NOT NULL DEFAULT NOW(), /* Date: When the course was first rated */
+    Updated_At TIMESTAMPTZ NOT NULL DEFAULT NOW(), /* Date: When the rating was last changed */
+    FOREIGN KEY (Course_ID) REFERENCES courses(Course_ID),
+    FOREIGN KEY (User_ID) REFERENCES users(User_ID),
+    UNIQUE (Course_ID, User_ID)
+);
+
+CREATE INDEX course_ratings_course_updated_at_idx ON course_ratings (Course_ID, Updated_At DESC);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS course_resources_course_score_idx;
+ALTER TABLE course_resources DROP COLUMN IF EXISTS Score;
+DROP TABLE IF EXISTS resource_votes;
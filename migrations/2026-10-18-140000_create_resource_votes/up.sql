@@ -0,0 +1,15 @@
+-- Your SQL goes here
+/* Up or down votes on course resources, one per user per resource */
+CREATE TABLE resource_votes (
+    Resource_ID UUID NOT NULL, /* UUID: Resource that was voted on */
+    User_ID UUID NOT NULL, /* UUID: User that voted */
+    Vote SMALLINT NOT NULL CHECK (Vote IN (-1, 1)), /* Number: 1 = Upvote, -1 = Downvote */
+    Created_At TIMESTAMPTZ NOT NULL DEFAULT NOW(), /* Date: When the vote was cast */
+    PRIMARY KEY (Resource_ID, User_ID),
+    FOREIGN KEY (Resource_ID) REFERENCES course_resources(Resource_ID) ON DELETE CASCADE,
+    FOREIGN KEY (User_ID) REFERENCES users(User_ID)
+);
+
+ALTER TABLE course_resources ADD COLUMN Score INT NOT NULL DEFAULT 0; /* Integer: Sum of the votes on the resource */
+
+CREATE INDEX course_resources_course_score_idx ON course_resources (Course_ID, Score DESC);
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+/* Comments on course resources, replies point to the comment they answer */
+CREATE TABLE resource_comments (
+    Comment_ID UUID PRIMARY KEY, /* UUID: Comment ID */
+    Resource_ID UUID NOT NULL, /* UUID: Resource the comment is on */
+    Parent_ID UUID, /* UUID: Comment this one replies to, null for top level comments */
+    Author_ID UUID NOT NULL, /* UUID: User that wrote the comment */
+    Body VARCHAR NOT NULL, /* String: Text of the comment */
+    Created_At TIMESTAMPTZ NOT NULL DEFAULT NOW(), /* Date: When the comment was posted */
+    Edited_At TIMESTAMPTZ, /* Date: When the comment was last edited */
+    Is_Deleted BOOLEAN NOT NULL DEFAULT FALSE, /* Boolean: Deleted by its author, kept so replies stay in their thread */
+    Is_Removed BOOLEAN NOT NULL DEFAULT FALSE, /* Boolean: Removed by a moderator */
+    FOREIGN KEY (Resource_ID) REFERENCES course_resources(Resource_ID) ON DELETE CASCADE,
+    FOREIGN KEY (Parent_ID) REFERENCES resource_comments(Comment_ID) ON DELETE CASCADE,
+    FOREIGN KEY (Author_ID) REFERENCES users(User_ID)
+);
+
+CREATE INDEX resource_comments_resource_created_at_idx ON resource_comments (Resource_ID, Created_At);
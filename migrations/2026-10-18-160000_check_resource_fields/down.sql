@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE courses DROP CONSTRAINT IF EXISTS courses_course_faculty_check;
+ALTER TABLE course_resources DROP CONSTRAINT IF EXISTS course_resources_semester_check;
+ALTER TABLE course_resources DROP CONSTRAINT IF EXISTS course_resources_resource_type_check;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+/* These columns map to enums in the backend (src/resource_types.rs, src/semesters.rs and src/faculties.rs) */
+ALTER TABLE course_resources ADD CONSTRAINT course_resources_resource_type_check CHECK (Resource_Type IN (0, 1));
+ALTER TABLE course_resources ADD CONSTRAINT course_resources_semester_check CHECK (Semester IN ('First', 'Second', 'Summer'));
+ALTER TABLE courses ADD CONSTRAINT courses_course_faculty_check CHECK (Course_Faculty BETWEEN 0 AND 9);
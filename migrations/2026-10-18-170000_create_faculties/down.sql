@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS courses_course_faculty_idx;
+ALTER TABLE courses DROP CONSTRAINT IF EXISTS courses_course_faculty_fkey;
+ALTER TABLE courses ADD CONSTRAINT courses_course_faculty_check CHECK (Course_Faculty BETWEEN 0 AND 9);
+DROP TABLE IF EXISTS faculties;
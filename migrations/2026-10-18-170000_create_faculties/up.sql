@@ -0,0 +1,23 @@
+-- Your SQL goes here
+/* The faculties courses belong to, the IDs match the Faculties enum in src/faculties.rs */
+CREATE TABLE faculties (
+    Faculty_ID SMALLINT PRIMARY KEY, /* Number: Faculty ID, as used in courses.Course_Faculty */
+    Name_En VARCHAR NOT NULL, /* String: English display name */
+    Name_Ar VARCHAR NOT NULL /* String: Arabic display name */
+);
+
+INSERT INTO faculties (Faculty_ID, Name_En, Name_Ar) VALUES
+    (0, 'Business School', 'كلية الأعمال'),
+    (1, 'Graduate School of Business', 'كلية الدراسات العليا للأعمال'),
+    (2, 'School of Applied Humanities', 'كلية العلوم الإنسانية التطبيقية'),
+    (3, 'School of Applied Medical Sciences', 'كلية العلوم الطبية التطبيقية'),
+    (4, 'School of Applied Technical Sciences', 'كلية العلوم التقنية التطبيقية'),
+    (5, 'School of Architecture and Built Environment', 'كلية العمارة والبيئة المبنية'),
+    (6, 'School of Electrical Engineering and Information Technology', 'كلية الهندسة الكهربائية وتكنولوجيا المعلومات'),
+    (7, 'School of Languages', 'كلية اللغات'),
+    (8, 'School of Natural Resources Engineering and Management', 'كلية هندسة وإدارة الموارد الطبيعية'),
+    (9, 'School of Nursing', 'كلية التمريض');
+
+ALTER TABLE courses DROP CONSTRAINT courses_course_faculty_check;
+ALTER TABLE courses ADD CONSTRAINT courses_course_faculty_fkey FOREIGN KEY (Course_Faculty) REFERENCES faculties(Faculty_ID);
+CREATE INDEX courses_course_faculty_idx ON courses (Course_Faculty);
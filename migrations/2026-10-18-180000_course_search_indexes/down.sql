@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS courses_course_id_trgm_idx;
+DROP INDEX IF EXISTS courses_course_name_trgm_idx;
+DROP INDEX IF EXISTS courses_course_name_tsv_idx;
+DROP EXTENSION IF EXISTS pg_trgm;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+/* Course search matches words of the name by prefix (tsvector), and typos in names and codes (trigrams) */
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX courses_course_name_tsv_idx ON courses USING GIN (to_tsvector('simple', Course_Name));
+CREATE INDEX courses_course_name_trgm_idx ON courses USING GIN (Course_Name gin_trgm_ops);
+CREATE INDEX courses_course_id_trgm_idx ON courses USING GIN (Course_ID gin_trgm_ops);
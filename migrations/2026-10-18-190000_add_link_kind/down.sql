@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE course_resource_links DROP COLUMN IF EXISTS Link_Kind;
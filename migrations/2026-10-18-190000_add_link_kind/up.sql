@@ -0,0 +1,16 @@
+-- Your SQL goes here
+/* Maps to LinkKind in the backend (src/link_kinds.rs), detected from the host of the URL */
+ALTER TABLE course_resource_links ADD COLUMN Link_Kind VARCHAR NOT NULL DEFAULT 'other'
+    CHECK (Link_Kind IN ('youtube', 'google_drive', 'telegram', 'discord', 'github', 'other')); /* String: What the link points to */
+
+/* Existing links are categorized the same way the backend does it for new ones */
+UPDATE course_resource_links SET Link_Kind = CASE
+    WHEN host ~ '(^|\.)(youtube\.com|youtu\.be)$' THEN 'youtube'
+    WHEN host ~ '^(drive|docs)\.google\.com$' THEN 'google_drive'
+    WHEN host ~ '(^|\.)(t\.me|telegram\.me|telegram\.org|telegram\.dog)$' THEN 'telegram'
+    WHEN host ~ '(^|\.)(discord\.gg|discord\.com|discordapp\.com)$' THEN 'discord'
+    WHEN host ~ '(^|\.)(github\.com|github\.io)$' THEN 'github'
+    ELSE 'other'
+END
+FROM (SELECT Link_ID AS id, lower(substring(Link_URL FROM '^[a-zA-Z][a-zA-Z0-9+.-]*://(?:[^/?#@]*@)?([^/?#:]+)')) AS host FROM course_resource_links) AS hosts
+WHERE Link_ID = hosts.id;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS course_resource_links_last_checked_idx;
+ALTER TABLE course_resource_links DROP COLUMN IF EXISTS Consecutive_Failures;
+ALTER TABLE course_resource_links DROP COLUMN IF EXISTS Last_Status;
+ALTER TABLE course_resource_links DROP COLUMN IF EXISTS Last_Checked;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+/* Results of the background link health checker (src/link_health.rs) */
+ALTER TABLE course_resource_links ADD COLUMN Last_Checked TIMESTAMPTZ; /* Date: When the link was last checked, NULL if it never was */
+ALTER TABLE course_resource_links ADD COLUMN Last_Status SMALLINT; /* Integer: HTTP status of the last check, NULL if the request itself failed */
+ALTER TABLE course_resource_links ADD COLUMN Consecutive_Failures INTEGER NOT NULL DEFAULT 0; /* Integer: Failed checks in a row, the link is hidden after too many */
+
+CREATE INDEX course_resource_links_last_checked_idx ON course_resource_links (Last_Checked NULLS FIRST) WHERE NOT Is_Hidden;
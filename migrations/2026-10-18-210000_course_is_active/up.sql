@@ -0,0 +1,3 @@
+-- Your SQL goes here
+/* Courses removed from Courses.json are kept, since resources and links reference them, but marked inactive */
+ALTER TABLE courses ADD COLUMN Is_Active BOOLEAN NOT NULL DEFAULT TRUE; /* Boolean: Whether the course is still in the catalog */
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE email_verification_codes DROP COLUMN IF EXISTS Attempts_Since;
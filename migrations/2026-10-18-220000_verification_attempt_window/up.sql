@@ -0,0 +1,3 @@
+-- Your SQL goes here
+/* Wrong guesses are counted per email over a window, so requesting a new code doesn't reset them */
+ALTER TABLE email_verification_codes ADD COLUMN Attempts_Since TIMESTAMPTZ NOT NULL DEFAULT NOW(); /* Date: Start of the window Attempts counts wrong guesses in */
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE reports DROP COLUMN IF EXISTS Hid_Target;
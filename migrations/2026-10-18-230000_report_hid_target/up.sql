@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE reports ADD COLUMN Hid_Target BOOLEAN NOT NULL DEFAULT FALSE; /* Boolean: Whether this report hid the item, dismissing the reports only shows the item again if one of them did */
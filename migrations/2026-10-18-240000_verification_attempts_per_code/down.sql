@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE email_verification_codes ADD COLUMN IF NOT EXISTS Attempts_Since TIMESTAMPTZ NOT NULL DEFAULT NOW(); /* Date: Start of the window Attempts counts wrong guesses in */
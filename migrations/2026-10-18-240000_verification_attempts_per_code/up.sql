@@ -0,0 +1,3 @@
+-- Your SQL goes here
+/* Wrong guesses are counted per code again, a new code starts over and the resend cooldown limits how often that can happen */
+ALTER TABLE email_verification_codes DROP COLUMN IF EXISTS Attempts_Since;
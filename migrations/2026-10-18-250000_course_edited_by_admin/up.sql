@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE courses ADD COLUMN Edited_By_Admin BOOLEAN NOT NULL DEFAULT FALSE; /* Boolean: Whether an admin changed the name or faculty, the catalog sync keeps those instead of overwriting them */
@@ -0,0 +1,417 @@
+//! User accounts, restricted to GJU students and staff.
+//! Signing up and signing in work the same way: a one-time code is emailed to a `@gju.edu.jo` address,
+//! and verifying it creates the account if it doesn't exist yet and issues a JWT for it.
+use axum::{async_trait, extract::{FromRequestParts, State}, http::{header::AUTHORIZATION, request::Parts, Method, Request, StatusCode}, middleware::Next, response::{IntoResponse, Response}, Json};
+use chrono::Utc;
+use diesel::prelude::*;
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::connection::{get_connection, DbConn};
+use crate::mail::{MailError, Mailer};
+use crate::models::{error_response, AuthResponse, EmailVerificationCode, RequestVerificationCode, User, VerifyEmailCode};
+use crate::schema::{email_verification_codes, users};
+use crate::state::AppState;
+
+const GJU_EMAIL_DOMAIN: &str = "@gju.edu.jo";
+const CODE_LIFETIME_MINUTES: i64 = 10;
+const CODE_RESEND_COOLDOWN_SECONDS: i64 = 60;
+/// Times a code can be tried before it stops working and a new one has to be requested
+const MAX_CODE_ATTEMPTS: i32 = 5;
+
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    sub: Uuid,
+    iat: i64,
+    exp: i64,
+}
+
+/// Signs the JWTs handed out to users.
+/// Configured with `JWT_SECRET` and `JWT_LIFETIME_DAYS` (default 30).
+/// `JWT_SECRET` can only be left out with `LOCAL_DEV_DEPLOYMENT`, which signs with a random secret instead
+pub struct TokenKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    lifetime: chrono::Duration,
+}
+
+impl TokenKeys {
+    pub fn from_env() -> Self {
+        let secret = match dotenvy::var("JWT_SECRET") {
+            Ok(secret) => secret.into_bytes(),
+            Err(_) => {
+                if dotenvy::var("LOCAL_DEV_DEPLOYMENT").is_err() {
+                    panic!("JWT_SECRET must be set");
+                }
+                eprintln!("JWT_SECRET is not set, using a random secret. Tokens won't survive restarts");
+                rand::thread_rng().gen::<[u8; 32]>().to_vec()
+            }
+        };
+        let lifetime_days: i64 = dotenvy::var("JWT_LIFETIME_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(30);
+
+        TokenKeys {
+            encoding: EncodingKey::from_secret(&secret),
+            decoding: DecodingKey::from_secret(&secret),
+            lifetime: chrono::Duration::days(lifetime_days),
+        }
+    }
+
+    pub fn issue(&self, user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
+        let now = Utc::now();
+        let claims = Claims { sub: user_id, iat: now.timestamp(), exp: (now + self.lifetime).timestamp() };
+        jsonwebtoken::encode(&Header::default(), &claims, &self.encoding)
+    }
+
+    /// Returns the id of the user the token was issued to, if it's valid and hasn't expired
+    pub fn verify(&self, token: &str) -> Result<Uuid, jsonwebtoken::errors::Error> {
+        let data = jsonwebtoken::decode::<Claims>(token, &self.decoding, &Validation::default())?;
+        Ok(data.claims.sub)
+    }
+}
+
+/// The user making the request, set by the `authenticate` middleware.
+/// Rejects with a 401 when the request isn't authenticated
+#[derive(Clone)]
+pub struct AuthenticatedUser(pub User);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthenticatedUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<AuthenticatedUser>()
+            .cloned()
+            .ok_or_else(|| error_response(StatusCode::UNAUTHORIZED, "You need to sign in to do this"))
+    }
+}
+
+async fn user_for_token(state: &AppState, token: &str) -> Result<User, Response> {
+    let user_id = state.tokens.verify(token)
+        .map_err(|_| error_response(StatusCode::UNAUTHORIZED, "Invalid or expired token"))?;
+    let mut conn = get_connection(&state.pool).await.map_err(IntoResponse::into_response)?;
+
+    match users::table.find(user_id).first::<User>(&mut conn) {
+        Ok(user) if user.is_banned => Err(error_response(StatusCode::FORBIDDEN, "This account has been banned")),
+        Ok(user) => Ok(user),
+        Err(diesel::result::Error::NotFound) => Err(error_response(StatusCode::UNAUTHORIZED, "This account no longer exists")),
+        Err(e) => Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+/// Requires a valid `Authorization: Bearer <token>` header on every request that can change something
+/// (anything other than GET, HEAD and OPTIONS), and exposes the user to handlers through `AuthenticatedUser`.
+/// Read-only requests stay public, but still get the user attached if they send a valid token
+pub async fn authenticate<B>(State(state): State<AppState>, mut request: Request<B>, next: Next<B>) -> Response {
+    let requires_user = !matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let token = request.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+
+    match token {
+        Some(token) => match user_for_token(&state, &token).await {
+            Ok(user) => { request.extensions_mut().insert(AuthenticatedUser(user)); },
+            Err(response) if requires_user => return response,
+            Err(_) => {}
+        },
+        None if requires_user => return error_response(StatusCode::UNAUTHORIZED, "You need to sign in to do this"),
+        None => {}
+    }
+
+    next.run(request).await
+}
+
+/// Lowercases and trims the email, returning `None` if it isn't a GJU address
+fn normalize_gju_email(email: &str) -> Option<String> {
+    let email = email.trim().to_lowercase();
+    let local_part = email.strip_suffix(GJU_EMAIL_DOMAIN)?;
+    if local_part.is_empty() || local_part.contains('@') || local_part.chars().any(char::is_whitespace) {
+        return None;
+    }
+
+    Some(email)
+}
+
+// Codes are only 6 digits, the email is mixed in so equal codes for different people don't hash the same
+fn hash_code(email: &str, code: &str) -> String {
+    let digest = Sha256::digest(format!("{}:{}", email, code.trim()).as_bytes());
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn get_verification_code_from_db(conn: &mut PgConnection, email: &str) -> Result<Option<EmailVerificationCode>, diesel::result::Error> {
+    email_verification_codes::table.find(email).first(conn).optional()
+}
+
+fn upsert_verification_code_into_db(conn: &mut PgConnection, code: &EmailVerificationCode) -> Result<usize, diesel::result::Error> {
+    diesel::insert_into(email_verification_codes::table)
+        .values(code)
+        .on_conflict(email_verification_codes::email)
+        .do_update()
+        .set(code)
+        .execute(conn)
+}
+
+/// Returns the user with this email, creating them if this is their first sign in
+fn get_or_create_user_in_db(conn: &mut PgConnection, email: &str) -> Result<User, diesel::result::Error> {
+    let new_user = User { user_id: Uuid::new_v4(), email: email.to_string(), created_at: Utc::now(), role: "user".to_string(), is_banned: false };
+    diesel::insert_into(users::table)
+        .values(&new_user)
+        .on_conflict(users::email)
+        .do_nothing()
+        .execute(conn)?;
+
+    users::table.filter(users::email.eq(email)).first(conn)
+}
+
+/// A signed in user with the admin role, rejects with a 403 for everyone else
+pub struct AdminUser(pub User);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AdminUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthenticatedUser(user) = AuthenticatedUser::from_request_parts(parts, state).await?;
+        if !user.is_admin() {
+            return Err(error_response(StatusCode::FORBIDDEN, "Only admins can do this"));
+        }
+
+        Ok(AdminUser(user))
+    }
+}
+
+pub async fn get_current_user(AuthenticatedUser(user): AuthenticatedUser) -> impl IntoResponse {
+    Json(user)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerificationError {
+    #[error("Only @gju.edu.jo email addresses can register")]
+    InvalidEmail,
+    #[error("A code was sent recently, wait a minute before requesting another one")]
+    Cooldown,
+    #[error("Too many wrong attempts, request a new code")]
+    TooManyAttempts,
+    #[error("No code was requested for this email")]
+    NoCode,
+    #[error("The code has expired, request a new one")]
+    Expired,
+    #[error("Invalid code")]
+    InvalidCode,
+    #[error(transparent)]
+    Mail(#[from] MailError),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error)
+}
+
+fn verification_error_response(e: VerificationError) -> Response {
+    match e {
+        VerificationError::Cooldown | VerificationError::TooManyAttempts => error_response(StatusCode::TOO_MANY_REQUESTS, e.to_string()),
+        VerificationError::Mail(_) | VerificationError::Database(_) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        _ => error_response(StatusCode::BAD_REQUEST, e.to_string())
+    }
+}
+
+/// Emails a new code to the address, replacing any pending one along with its attempts
+async fn send_verification_code(conn: &mut PgConnection, mailer: &dyn Mailer, email: &str) -> Result<(), VerificationError> {
+    let email = normalize_gju_email(email).ok_or(VerificationError::InvalidEmail)?;
+    let code = format!("{:06}", rand::thread_rng().gen_range(0..1_000_000));
+    let now = Utc::now();
+
+    conn.transaction(|conn| {
+        let verification_code = EmailVerificationCode {
+            email: email.clone(),
+            code_hash: hash_code(&email, &code),
+            created_at: now,
+            expires_at: now + chrono::Duration::minutes(CODE_LIFETIME_MINUTES),
+            attempts: 0
+        };
+
+        // Locked so two requests can't both get past the cooldown
+        let existing = email_verification_codes::table.find(&email).for_update().first::<EmailVerificationCode>(conn).optional()?;
+        if let Some(existing) = existing {
+            if existing.created_at + chrono::Duration::seconds(CODE_RESEND_COOLDOWN_SECONDS) > now {
+                return Err(VerificationError::Cooldown);
+            }
+        }
+
+        upsert_verification_code_into_db(conn, &verification_code)?;
+        Ok(())
+    })?;
+
+    let body = format!("Your GJU Files verification code is {}\n\nIt expires in {} minutes. If you didn't request it, you can ignore this email.", code, CODE_LIFETIME_MINUTES);
+    mailer.send(&email, "Your GJU Files verification code", &body).await?;
+    Ok(())
+}
+
+/// Checks the code sent to the email, returning the (possibly new) user it belongs to.
+/// The attempt is counted before comparing, so concurrent guesses can't go past `MAX_CODE_ATTEMPTS`
+fn check_verification_code(conn: &mut PgConnection, email: &str, code: &str) -> Result<User, VerificationError> {
+    let email = normalize_gju_email(email).ok_or(VerificationError::InvalidEmail)?;
+
+    let code_hash = diesel::update(email_verification_codes::table.find(&email))
+        .filter(email_verification_codes::attempts.lt(MAX_CODE_ATTEMPTS))
+        .filter(email_verification_codes::expires_at.gt(Utc::now()))
+        .set(email_verification_codes::attempts.eq(email_verification_codes::attempts + 1))
+        .returning(email_verification_codes::code_hash)
+        .get_result::<String>(conn)
+        .optional()?;
+
+    let Some(code_hash) = code_hash else {
+        return Err(match get_verification_code_from_db(conn, &email)? {
+            None => VerificationError::NoCode,
+            Some(existing) if existing.expires_at <= Utc::now() => VerificationError::Expired,
+            Some(_) => VerificationError::TooManyAttempts
+        });
+    };
+
+    if code_hash != hash_code(&email, code) {
+        return Err(VerificationError::InvalidCode);
+    }
+
+    Ok(conn.transaction(|conn| {
+        diesel::delete(email_verification_codes::table.find(&email)).execute(conn)?;
+        get_or_create_user_in_db(conn, &email)
+    })?)
+}
+
+pub async fn request_verification_code(State(state): State<AppState>, DbConn(mut conn): DbConn, Json(payload): Json<RequestVerificationCode>) -> Result<impl IntoResponse, StatusCode> {
+    match send_verification_code(&mut conn, state.mailer.as_ref(), &payload.email).await {
+        Ok(()) => Ok(StatusCode::OK.into_response()),
+        Err(e) => Ok(verification_error_response(e))
+    }
+}
+
+pub async fn verify_email_code(State(state): State<AppState>, DbConn(mut conn): DbConn, Json(payload): Json<VerifyEmailCode>) -> Result<impl IntoResponse, StatusCode> {
+    let user = match check_verification_code(&mut conn, &payload.email, &payload.code) {
+        Ok(user) => user,
+        Err(e) => return Ok(verification_error_response(e))
+    };
+
+    match state.tokens.issue(user.user_id) {
+        Ok(token) => Ok(Json(AuthResponse { token, user }).into_response()),
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail::MemoryMailer;
+    use crate::test_support::test_connection;
+
+    const WRONG_CODE: &str = "wrong!";
+
+    fn test_email() -> String {
+        format!("test-{}@gju.edu.jo", Uuid::new_v4().simple())
+    }
+
+    // The code in the last email sent to the address
+    fn sent_code(mailer: &MemoryMailer, email: &str) -> String {
+        let sent = mailer.sent.lock().unwrap();
+        let body = &sent.iter().rev().find(|sent| sent.to == email).expect("No email was sent").body;
+        body.split_whitespace().find(|word| word.len() == 6 && word.chars().all(|c| c.is_ascii_digit())).unwrap().to_string()
+    }
+
+    fn move_back(conn: &mut PgConnection, email: &str, column: &str, minutes: i64) {
+        diesel::sql_query(format!("UPDATE email_verification_codes SET {0} = {0} - make_interval(mins => $1) WHERE email = $2", column))
+            .bind::<diesel::sql_types::Integer, _>(minutes as i32)
+            .bind::<diesel::sql_types::Text, _>(email)
+            .execute(conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn only_accepts_gju_emails() {
+        assert_eq!(normalize_gju_email("  Student@GJU.edu.jo ").as_deref(), Some("student@gju.edu.jo"));
+        for email in ["student@gmail.com", "@gju.edu.jo", "a@b@gju.edu.jo", "a b@gju.edu.jo", "student@gju.edu.jo.evil.com", "student@notgju.edu.jo"] {
+            assert_eq!(normalize_gju_email(email), None, "{}", email);
+        }
+    }
+
+    #[tokio::test]
+    async fn signs_in_with_the_emailed_code() {
+        let Some(mut conn) = test_connection() else { return };
+        let mailer = MemoryMailer::default();
+        let email = test_email();
+
+        assert!(matches!(send_verification_code(&mut conn, &mailer, "student@gmail.com").await, Err(VerificationError::InvalidEmail)));
+        send_verification_code(&mut conn, &mailer, &email.to_uppercase()).await.unwrap();
+        assert_eq!(mailer.sent.lock().unwrap()[0].subject, "Your GJU Files verification code");
+
+        let user = check_verification_code(&mut conn, &email, &sent_code(&mailer, &email)).unwrap();
+        assert_eq!(user.email, email);
+        // Codes only work once
+        assert!(matches!(check_verification_code(&mut conn, &email, &sent_code(&mailer, &email)), Err(VerificationError::NoCode)));
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_codes() {
+        let Some(mut conn) = test_connection() else { return };
+        let mailer = MemoryMailer::default();
+        let email = test_email();
+        send_verification_code(&mut conn, &mailer, &email).await.unwrap();
+        move_back(&mut conn, &email, "expires_at", CODE_LIFETIME_MINUTES + 1);
+
+        assert!(matches!(check_verification_code(&mut conn, &email, &sent_code(&mailer, &email)), Err(VerificationError::Expired)));
+        assert_eq!(get_verification_code_from_db(&mut conn, &email).unwrap().unwrap().attempts, 0);
+    }
+
+    #[tokio::test]
+    async fn throttles_code_requests() {
+        let Some(mut conn) = test_connection() else { return };
+        let mailer = MemoryMailer::default();
+        let email = test_email();
+        send_verification_code(&mut conn, &mailer, &email).await.unwrap();
+
+        assert!(matches!(send_verification_code(&mut conn, &mailer, &email).await, Err(VerificationError::Cooldown)));
+        assert_eq!(mailer.sent.lock().unwrap().len(), 1);
+
+        move_back(&mut conn, &email, "created_at", 2);
+        send_verification_code(&mut conn, &mailer, &email).await.unwrap();
+        assert_eq!(mailer.sent.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn locks_out_after_too_many_wrong_codes() {
+        let Some(mut conn) = test_connection() else { return };
+        let mailer = MemoryMailer::default();
+        let email = test_email();
+        send_verification_code(&mut conn, &mailer, &email).await.unwrap();
+
+        for _ in 0..MAX_CODE_ATTEMPTS {
+            assert!(matches!(check_verification_code(&mut conn, &email, WRONG_CODE), Err(VerificationError::InvalidCode)));
+        }
+        assert!(matches!(check_verification_code(&mut conn, &email, &sent_code(&mailer, &email)), Err(VerificationError::TooManyAttempts)));
+
+        // A new code can be requested once the cooldown is over and starts with no attempts
+        assert!(matches!(send_verification_code(&mut conn, &mailer, &email).await, Err(VerificationError::Cooldown)));
+        move_back(&mut conn, &email, "created_at", 2);
+        send_verification_code(&mut conn, &mailer, &email).await.unwrap();
+        assert_eq!(get_verification_code_from_db(&mut conn, &email).unwrap().unwrap().attempts, 0);
+        assert!(check_verification_code(&mut conn, &email, &sent_code(&mailer, &email)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn resending_replaces_the_code() {
+        let Some(mut conn) = test_connection() else { return };
+        let mailer = MemoryMailer::default();
+        let email = test_email();
+        send_verification_code(&mut conn, &mailer, &email).await.unwrap();
+        let old_code = sent_code(&mailer, &email);
+        assert!(matches!(check_verification_code(&mut conn, &email, WRONG_CODE), Err(VerificationError::InvalidCode)));
+
+        move_back(&mut conn, &email, "created_at", 2);
+        send_verification_code(&mut conn, &mailer, &email).await.unwrap();
+        let new_code = sent_code(&mailer, &email);
+
+        if old_code != new_code {
+            assert!(matches!(check_verification_code(&mut conn, &email, &old_code), Err(VerificationError::InvalidCode)));
+        }
+        assert!(check_verification_code(&mut conn, &email, &new_code).is_ok());
+    }
+}
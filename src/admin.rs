@@ -0,0 +1,316 @@
+//! Moderation API for admins, mounted at `/v1/admin`.
+//! Every action taken here is recorded in `admin_audit_log`.
+use axum::{extract::{Path, Query, State}, http::StatusCode, response::IntoResponse, routing::{delete, get, patch, post}, Json, Router};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::accounts::AdminUser;
+use crate::comments::remove_comment_in_db;
+use crate::connection::DbConn;
+use crate::course_initialization::{read_courses_json, sync_courses};
+use crate::course_retreival::{delete_course_link_from_db, delete_course_resource_from_db, sanitize_page_input};
+use crate::models::{error_response, AdminAuditLogEntry, AdminPageQuery, AdminRecentUploadsResponse, AdminReportsQuery, BanUserRequest, Course, CourseResource, CourseResourceLink, EditCourse, Report, ReportDecision, ReportTargetType, ReviewReport, SetHiddenRequest, User};
+use crate::reports::{clear_report_hide, lock_report_target, report_target_type, set_report_target_hidden};
+use crate::resource_uploads::delete_resource_files_from_storage;
+use crate::schema::{admin_audit_log, course_resource_links, course_resources, courses, reports, users};
+use crate::state::AppState;
+
+const ADMIN_PAGE_SIZE: i64 = 50;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/uploads", get(get_recent_uploads))
+        .route("/course_resource/:resource_id", delete(delete_resource))
+        .route("/course_resource/:resource_id/visibility", post(set_resource_hidden))
+        .route("/course_link/:link_id", delete(delete_link))
+        .route("/course_link/:link_id/visibility", post(set_link_hidden))
+        .route("/comments/:comment_id", delete(remove_comment))
+        .route("/users/:user_id/ban", post(ban_user))
+        .route("/courses/sync", post(sync_course_catalog))
+        .route("/courses/:course_id", patch(edit_course))
+        .route("/reports", get(get_reports))
+        .route("/reports/:report_id/review", post(review_report))
+        .route("/audit_log", get(get_audit_log))
+}
+
+fn record_admin_action(conn: &mut PgConnection, admin: &User, action: &str, target_type: &str, target_id: &str, details: Option<String>) -> Result<(), diesel::result::Error> {
+    let entry = AdminAuditLogEntry {
+        audit_id: Uuid::new_v4(),
+        admin_id: admin.user_id,
+        action: action.to_string(),
+        target_type: target_type.to_string(),
+        target_id: target_id.to_string(),
+        details,
+        created_at: chrono::Utc::now()
+    };
+
+    diesel::insert_into(admin_audit_log::table).values(entry).execute(conn)?;
+    Ok(())
+}
+
+fn not_found_or_error(e: diesel::result::Error, not_found: String) -> axum::response::Response {
+    match e {
+        diesel::result::Error::NotFound => error_response(StatusCode::NOT_FOUND, not_found),
+        e => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+/// Newest resources and links across all courses, including hidden ones
+async fn get_recent_uploads(_admin: AdminUser, DbConn(mut conn): DbConn, Query(query): Query<AdminPageQuery>) -> Result<impl IntoResponse, StatusCode> {
+    let offset = (sanitize_page_input(query.page) - 1) * ADMIN_PAGE_SIZE;
+
+    let resources = course_resources::table
+        .order(course_resources::dateuploaded.desc())
+        .limit(ADMIN_PAGE_SIZE)
+        .offset(offset)
+        .select(CourseResource::as_select())
+        .load(&mut conn);
+
+    let links = course_resource_links::table
+        .order(course_resource_links::date_added.desc())
+        .limit(ADMIN_PAGE_SIZE)
+        .offset(offset)
+        .select(CourseResourceLink::as_select())
+        .load(&mut conn);
+
+    match (resources, links) {
+        (Ok(resources), Ok(links)) => Ok(Json(AdminRecentUploadsResponse { resources, links }).into_response()),
+        (Err(e), _) | (_, Err(e)) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+async fn delete_resource(State(state): State<AppState>, AdminUser(admin): AdminUser, DbConn(mut conn): DbConn, Path(resource_id): Path<Uuid>) -> Result<impl IntoResponse, StatusCode> {
+    let deleted = conn.transaction(|conn| {
+        let deleted = delete_course_resource_from_db(conn, resource_id)?;
+        record_admin_action(conn, &admin, "delete_resource", "resource", &resource_id.to_string(), Some(deleted.0.title.clone()))?;
+        Ok::<_, diesel::result::Error>(deleted)
+    });
+
+    match deleted {
+        Ok((resource, files)) => {
+            delete_resource_files_from_storage(state.storage.as_ref(), &resource.course_id, &files).await;
+            Ok(StatusCode::OK.into_response())
+        },
+        Err(e) => Ok(not_found_or_error(e, format!("Resource with id {} not found", resource_id)))
+    }
+}
+
+async fn set_resource_hidden(AdminUser(admin): AdminUser, DbConn(mut conn): DbConn, Path(resource_id): Path<Uuid>, Json(payload): Json<SetHiddenRequest>) -> Result<impl IntoResponse, StatusCode> {
+    let updated = conn.transaction(|conn| {
+        let resource = diesel::update(course_resources::table.find(resource_id))
+            .set(course_resources::is_hidden.eq(payload.hidden))
+            .returning(CourseResource::as_returning())
+            .get_result(conn)?;
+        clear_report_hide(conn, ReportTargetType::Resource, resource_id)?;
+        let action = if payload.hidden { "hide_resource" } else { "unhide_resource" };
+        record_admin_action(conn, &admin, action, "resource", &resource_id.to_string(), None)?;
+        Ok::<_, diesel::result::Error>(resource)
+    });
+
+    match updated {
+        Ok(resource) => Ok(Json(resource).into_response()),
+        Err(e) => Ok(not_found_or_error(e, format!("Resource with id {} not found", resource_id)))
+    }
+}
+
+async fn delete_link(AdminUser(admin): AdminUser, DbConn(mut conn): DbConn, Path(link_id): Path<Uuid>) -> Result<impl IntoResponse, StatusCode> {
+    let deleted = conn.transaction(|conn| {
+        let link = delete_course_link_from_db(conn, link_id)?;
+        record_admin_action(conn, &admin, "delete_link", "link", &link_id.to_string(), Some(link.link_url.clone()))?;
+        Ok::<_, diesel::result::Error>(link)
+    });
+
+    match deleted {
+        Ok(_) => Ok(StatusCode::OK.into_response()),
+        Err(e) => Ok(not_found_or_error(e, format!("Link with id {} not found", link_id)))
+    }
+}
+
+async fn set_link_hidden(AdminUser(admin): AdminUser, DbConn(mut conn): DbConn, Path(link_id): Path<Uuid>, Json(payload): Json<SetHiddenRequest>) -> Result<impl IntoResponse, StatusCode> {
+    let updated = conn.transaction(|conn| {
+        // A moderator deciding on the link starts its failed health checks over
+        let link = diesel::update(course_resource_links::table.find(link_id))
+            .set((course_resource_links::is_hidden.eq(payload.hidden), course_resource_links::consecutive_failures.eq(0)))
+            .returning(CourseResourceLink::as_returning())
+            .get_result(conn)?;
+        clear_report_hide(conn, ReportTargetType::Link, link_id)?;
+        let action = if payload.hidden { "hide_link" } else { "unhide_link" };
+        record_admin_action(conn, &admin, action, "link", &link_id.to_string(), None)?;
+        Ok::<_, diesel::result::Error>(link)
+    });
+
+    match updated {
+        Ok(link) => Ok(Json(link).into_response()),
+        Err(e) => Ok(not_found_or_error(e, format!("Link with id {} not found", link_id)))
+    }
+}
+
+async fn remove_comment(AdminUser(admin): AdminUser, DbConn(mut conn): DbConn, Path(comment_id): Path<Uuid>) -> Result<impl IntoResponse, StatusCode> {
+    let removed = conn.transaction(|conn| {
+        let comment = remove_comment_in_db(conn, comment_id)?;
+        record_admin_action(conn, &admin, "remove_comment", "comment", &comment_id.to_string(), Some(comment.body.clone()))?;
+        Ok::<_, diesel::result::Error>(comment)
+    });
+
+    match removed {
+        Ok(_) => Ok(StatusCode::OK.into_response()),
+        Err(e) => Ok(not_found_or_error(e, format!("Comment with id {} not found", comment_id)))
+    }
+}
+
+async fn ban_user(AdminUser(admin): AdminUser, DbConn(mut conn): DbConn, Path(user_id): Path<Uuid>, Json(payload): Json<BanUserRequest>) -> Result<impl IntoResponse, StatusCode> {
+    // None when the user is an admin, checked on the locked row so a role change can't slip in before the ban
+    let updated = conn.transaction(|conn| {
+        let target = users::table.find(user_id).for_update().first::<User>(conn)?;
+        if target.is_admin() {
+            return Ok(None);
+        }
+
+        let user = diesel::update(users::table.find(user_id))
+            .set(users::is_banned.eq(payload.banned))
+            .returning(User::as_returning())
+            .get_result(conn)?;
+        let action = if payload.banned { "ban_user" } else { "unban_user" };
+        record_admin_action(conn, &admin, action, "user", &user_id.to_string(), payload.reason)?;
+        Ok::<_, diesel::result::Error>(Some(user))
+    });
+
+    match updated {
+        Ok(Some(user)) => Ok(Json(user).into_response()),
+        Ok(None) => Ok(error_response(StatusCode::BAD_REQUEST, "Admins can't be banned")),
+        Err(e) => Ok(not_found_or_error(e, format!("User with id {} not found", user_id)))
+    }
+}
+
+async fn edit_course(State(state): State<AppState>, AdminUser(admin): AdminUser, DbConn(mut conn): DbConn, Path(course_id): Path<String>, Json(payload): Json<EditCourse>) -> Result<impl IntoResponse, StatusCode> {
+    if payload.course_name.is_none() && payload.course_faculty.is_none() {
+        return Ok(error_response(StatusCode::BAD_REQUEST, "Nothing to update"));
+    }
+
+    if payload.course_name.as_ref().is_some_and(|name| name.trim().is_empty()) {
+        return Ok(error_response(StatusCode::BAD_REQUEST, "Course name can't be empty"));
+    }
+
+    let details = serde_json::json!({ "course_name": payload.course_name, "course_faculty": payload.course_faculty }).to_string();
+    let updated = conn.transaction(|conn| {
+        let course = diesel::update(courses::table.find(&course_id))
+            .set((&payload, courses::edited_by_admin.eq(true)))
+            .returning(Course::as_returning())
+            .get_result(conn)?;
+        record_admin_action(conn, &admin, "edit_course", "course", &course_id, Some(details))?;
+        Ok::<_, diesel::result::Error>(course)
+    });
+
+    match updated {
+        Ok(course) => {
+            if let Err(e) = state.course_suggestions.refresh(&mut conn) {
+                eprintln!("Failed to refresh course suggestions: {}", e);
+            }
+            Ok(Json(course).into_response())
+        },
+        Err(e) => Ok(not_found_or_error(e, format!("Course with id {} not found", course_id)))
+    }
+}
+
+/// Syncs the courses with `COURSES_JSON_PATH` without restarting the backend, responding with what changed
+async fn sync_course_catalog(State(state): State<AppState>, AdminUser(admin): AdminUser, DbConn(mut conn): DbConn) -> Result<impl IntoResponse, StatusCode> {
+    let courses_data = match read_courses_json() {
+        Ok(courses_data) => courses_data,
+        Err(e) => return Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read the course catalog: {}", e)))
+    };
+
+    let synced = conn.transaction(|conn| {
+        let report = sync_courses(conn, courses_data)?;
+        let details = serde_json::json!({ "added": report.added.len(), "changed": report.changed.len(), "removed": report.removed.len(), "kept_edits": report.kept_edits.len() }).to_string();
+        record_admin_action(conn, &admin, "sync_courses", "course", "catalog", Some(details))?;
+        Ok::<_, diesel::result::Error>(report)
+    });
+
+    match synced {
+        Ok(report) => {
+            if let Err(e) = state.course_suggestions.refresh(&mut conn) {
+                eprintln!("Failed to refresh course suggestions: {}", e);
+            }
+            Ok(Json(report).into_response())
+        },
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+/// Reports with the given status (pending by default), oldest first so the queue is worked through in order
+async fn get_reports(_admin: AdminUser, DbConn(mut conn): DbConn, Query(query): Query<AdminReportsQuery>) -> Result<impl IntoResponse, StatusCode> {
+    let status = query.status.unwrap_or(Report::PENDING.to_string());
+    if ![Report::PENDING, Report::UPHELD, Report::DISMISSED].contains(&status.as_str()) {
+        return Ok(error_response(StatusCode::BAD_REQUEST, "Invalid status (Must be pending, upheld or dismissed)"));
+    }
+
+    let found = reports::table
+        .filter(reports::status.eq(status))
+        .order(reports::created_at.asc())
+        .limit(ADMIN_PAGE_SIZE)
+        .offset((sanitize_page_input(query.page) - 1) * ADMIN_PAGE_SIZE)
+        .select(Report::as_select())
+        .load(&mut conn);
+
+    match found {
+        Ok(found) => Ok(Json(found).into_response()),
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+/// Settles every pending report on the same item as this one.
+/// Upholding keeps the item hidden (hiding it if it wasn't yet).
+/// Dismissing shows the item again only if the reports are what hid it, not if a moderator or the link checker did
+async fn review_report(AdminUser(admin): AdminUser, DbConn(mut conn): DbConn, Path(report_id): Path<Uuid>, Json(payload): Json<ReviewReport>) -> Result<impl IntoResponse, StatusCode> {
+    let report = match reports::table.find(report_id).first::<Report>(&mut conn) {
+        Ok(report) => report,
+        Err(e) => return Ok(not_found_or_error(e, format!("Report with id {} not found", report_id)))
+    };
+
+    let Some(target_type) = report_target_type(&report) else {
+        return Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("Report has an unknown target type {}", report.target_type)));
+    };
+
+    let (status, action) = match payload.decision {
+        ReportDecision::Uphold => (Report::UPHELD, "uphold_reports"),
+        ReportDecision::Dismiss => (Report::DISMISSED, "dismiss_reports")
+    };
+
+    let reviewed = conn.transaction(|conn| {
+        lock_report_target(conn, target_type, report.target_id)?;
+        let reviewed = diesel::update(reports::table)
+            .filter(reports::target_type.eq(&report.target_type))
+            .filter(reports::target_id.eq(report.target_id))
+            .filter(reports::status.eq(Report::PENDING))
+            .set(reports::status.eq(status))
+            .returning(Report::as_returning())
+            .get_results(conn)?;
+
+        match payload.decision {
+            ReportDecision::Uphold => { set_report_target_hidden(conn, target_type, report.target_id, true)?; },
+            ReportDecision::Dismiss if reviewed.iter().any(|report| report.hid_target) => { set_report_target_hidden(conn, target_type, report.target_id, false)?; },
+            ReportDecision::Dismiss => {}
+        }
+        record_admin_action(conn, &admin, action, &report.target_type, &report.target_id.to_string(), Some(format!("{} reports", reviewed.len())))?;
+        Ok::<Vec<Report>, diesel::result::Error>(reviewed)
+    });
+
+    match reviewed {
+        Ok(reviewed) => Ok(Json(reviewed).into_response()),
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+async fn get_audit_log(_admin: AdminUser, DbConn(mut conn): DbConn, Query(query): Query<AdminPageQuery>) -> Result<impl IntoResponse, StatusCode> {
+    let entries = admin_audit_log::table
+        .order(admin_audit_log::created_at.desc())
+        .limit(ADMIN_PAGE_SIZE)
+        .offset((sanitize_page_input(query.page) - 1) * ADMIN_PAGE_SIZE)
+        .select(AdminAuditLogEntry::as_select())
+        .load(&mut conn);
+
+    match entries {
+        Ok(entries) => Ok(Json(entries).into_response()),
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
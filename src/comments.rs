@@ -0,0 +1,193 @@
+//! Comment threads on course resources.
+//! Authors can edit and delete their comments, moderators remove them through the admin API.
+//! Neither actually deletes the row so the replies under a comment keep their place in the thread.
+use std::collections::HashMap;
+
+use axum::{extract::{Path, Query}, http::StatusCode, response::IntoResponse, Json};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::accounts::AuthenticatedUser;
+use crate::connection::DbConn;
+use crate::course_retreival::sanitize_page_input;
+use crate::models::{error_response, EditResourceComment, GetResourceCommentsQuery, GetResourceCommentsResponse, PostResourceComment, ResourceComment, ResourceCommentResponse};
+use crate::schema::{course_resources, resource_comments};
+
+const COMMENTS_PER_PAGE: i64 = 50;
+const MAX_COMMENT_LENGTH: usize = 5000;
+
+/// How many visible comments each of the given resources has, resources without any are left out
+pub fn get_comment_counts_from_db(conn: &mut PgConnection, resource_ids: &[Uuid]) -> Result<HashMap<Uuid, i64>, diesel::result::Error> {
+    let counts = resource_comments::table
+        .filter(resource_comments::resource_id.eq_any(resource_ids))
+        .filter(resource_comments::is_deleted.eq(false).and(resource_comments::is_removed.eq(false)))
+        .group_by(resource_comments::resource_id)
+        .select((resource_comments::resource_id, count_star()))
+        .load::<(Uuid, i64)>(conn)?;
+
+    Ok(counts.into_iter().collect())
+}
+
+/// Marks a comment as removed by a moderator
+pub fn remove_comment_in_db(conn: &mut PgConnection, comment_id: Uuid) -> Result<ResourceComment, diesel::result::Error> {
+    diesel::update(resource_comments::table.find(comment_id))
+        .set(resource_comments::is_removed.eq(true))
+        .returning(ResourceComment::as_returning())
+        .get_result(conn)
+}
+
+fn visible_resource_exists(conn: &mut PgConnection, resource_id: Uuid) -> Result<bool, diesel::result::Error> {
+    let count = course_resources::table
+        .find(resource_id)
+        .filter(course_resources::is_hidden.eq(false))
+        .select(count_star())
+        .get_result::<i64>(conn)?;
+
+    Ok(count > 0)
+}
+
+fn validate_comment_body(body: &str) -> Result<String, String> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err("Comment can't be empty".to_string());
+    }
+
+    if body.chars().count() > MAX_COMMENT_LENGTH {
+        return Err(format!("Comment can't be longer than {} characters", MAX_COMMENT_LENGTH));
+    }
+
+    Ok(body.to_string())
+}
+
+/// Finds a comment of the resource that the user wrote and can still change
+fn find_own_comment(conn: &mut PgConnection, resource_id: Uuid, comment_id: Uuid, user_id: Uuid) -> Result<ResourceComment, axum::response::Response> {
+    let comment = resource_comments::table
+        .find(comment_id)
+        .filter(resource_comments::resource_id.eq(resource_id))
+        .first::<ResourceComment>(conn);
+
+    match comment {
+        Ok(comment) if comment.is_deleted || comment.is_removed => Err(error_response(StatusCode::NOT_FOUND, format!("Comment with id {} not found", comment_id))),
+        Ok(comment) if comment.author_id != user_id => Err(error_response(StatusCode::FORBIDDEN, "You can only change your own comments")),
+        Ok(comment) => Ok(comment),
+        Err(diesel::result::Error::NotFound) => Err(error_response(StatusCode::NOT_FOUND, format!("Comment with id {} not found", comment_id))),
+        Err(e) => Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+pub async fn get_resource_comments(DbConn(mut conn): DbConn, Path(resource_id): Path<Uuid>, Query(query): Query<GetResourceCommentsQuery>) -> Result<impl IntoResponse, StatusCode> {
+    match visible_resource_exists(&mut conn, resource_id) {
+        Ok(true) => {},
+        Ok(false) => return Ok(error_response(StatusCode::NOT_FOUND, format!("Resource with id {} not found", resource_id))),
+        Err(e) => return Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+
+    let comments = resource_comments::table
+        .filter(resource_comments::resource_id.eq(resource_id))
+        .order(resource_comments::created_at.asc())
+        .limit(COMMENTS_PER_PAGE)
+        .offset((sanitize_page_input(query.page) - 1) * COMMENTS_PER_PAGE)
+        .select(ResourceComment::as_select())
+        .load(&mut conn);
+
+    let total_entries = resource_comments::table
+        .filter(resource_comments::resource_id.eq(resource_id))
+        .select(count_star())
+        .get_result::<i64>(&mut conn);
+
+    let total_comments = get_comment_counts_from_db(&mut conn, &[resource_id]).map(|counts| counts.get(&resource_id).copied().unwrap_or(0));
+
+    match (comments, total_entries, total_comments) {
+        (Ok(comments), Ok(total_entries), Ok(total_comments)) => {
+            let comments = comments.into_iter().map(ResourceCommentResponse::from).collect();
+            Ok(Json(GetResourceCommentsResponse { comments, total_comments, total_entries }).into_response())
+        },
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+pub async fn post_resource_comment(AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Path(resource_id): Path<Uuid>, Json(payload): Json<PostResourceComment>) -> Result<impl IntoResponse, StatusCode> {
+    let body = match validate_comment_body(&payload.body) {
+        Ok(body) => body,
+        Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, e))
+    };
+
+    match visible_resource_exists(&mut conn, resource_id) {
+        Ok(true) => {},
+        Ok(false) => return Ok(error_response(StatusCode::NOT_FOUND, format!("Resource with id {} not found", resource_id))),
+        Err(e) => return Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+
+    if let Some(parent_id) = payload.parent_id {
+        let parent = resource_comments::table
+            .find(parent_id)
+            .filter(resource_comments::resource_id.eq(resource_id))
+            .first::<ResourceComment>(&mut conn)
+            .optional();
+
+        match parent {
+            Ok(Some(parent)) if !parent.is_deleted && !parent.is_removed => {},
+            Ok(_) => return Ok(error_response(StatusCode::BAD_REQUEST, "The comment you're replying to doesn't exist on this resource")),
+            Err(e) => return Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+    }
+
+    let comment = ResourceComment {
+        comment_id: Uuid::new_v4(),
+        resource_id,
+        parent_id: payload.parent_id,
+        author_id: user.user_id,
+        body,
+        created_at: chrono::Utc::now(),
+        edited_at: None,
+        is_deleted: false,
+        is_removed: false
+    };
+
+    let inserted = diesel::insert_into(resource_comments::table)
+        .values(comment)
+        .returning(ResourceComment::as_returning())
+        .get_result(&mut conn);
+
+    match inserted {
+        Ok(comment) => Ok((StatusCode::CREATED, Json(ResourceCommentResponse::from(comment))).into_response()),
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+pub async fn edit_resource_comment(AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Path((resource_id, comment_id)): Path<(Uuid, Uuid)>, Json(payload): Json<EditResourceComment>) -> Result<impl IntoResponse, StatusCode> {
+    let body = match validate_comment_body(&payload.body) {
+        Ok(body) => body,
+        Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, e))
+    };
+
+    if let Err(response) = find_own_comment(&mut conn, resource_id, comment_id, user.user_id) {
+        return Ok(response);
+    }
+
+    let updated = diesel::update(resource_comments::table.find(comment_id))
+        .set((resource_comments::body.eq(body), resource_comments::edited_at.eq(chrono::Utc::now())))
+        .returning(ResourceComment::as_returning())
+        .get_result(&mut conn);
+
+    match updated {
+        Ok(comment) => Ok(Json(ResourceCommentResponse::from(comment)).into_response()),
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+pub async fn delete_resource_comment(AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Path((resource_id, comment_id)): Path<(Uuid, Uuid)>) -> Result<impl IntoResponse, StatusCode> {
+    if let Err(response) = find_own_comment(&mut conn, resource_id, comment_id, user.user_id) {
+        return Ok(response);
+    }
+
+    let deleted = diesel::update(resource_comments::table.find(comment_id))
+        .set(resource_comments::is_deleted.eq(true))
+        .execute(&mut conn);
+
+    match deleted {
+        Ok(_) => Ok(StatusCode::OK.into_response()),
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
@@ -1,14 +1,71 @@
-use std::env;
-
-use dotenvy::dotenv;
-use diesel::{Connection, PgConnection};
-
-// TODO: Connection pooling
-pub fn establish_connection() -> Result<PgConnection, diesel::result::ConnectionError> {
-    dotenv().ok();
-    let db_url = env::var("DATABASE_URL")
-    .map_err(
-        |_| diesel::result::ConnectionError::BadConnection("DATABASE_URL not set".into())
-    )?;
-    PgConnection::establish(&db_url)
+use std::time::Duration;
+
+use axum::{async_trait, extract::{FromRef, FromRequestParts}, http::{request::Parts, StatusCode}, response::{IntoResponse, Response}, Json};
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+use diesel::PgConnection;
+
+use crate::models::ErrorResponse;
+
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+pub type DbConnection = PooledConnection<ConnectionManager<PgConnection>>;
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    dotenvy::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Creates the connection pool shared by all handlers.
+/// Connections are opened lazily, so the server still starts if Postgres is briefly unavailable.
+///
+/// Configured with `DATABASE_POOL_MAX_SIZE` (default 10), `DATABASE_POOL_CONNECTION_TIMEOUT_SECS` (default 5)
+/// and `DATABASE_POOL_IDLE_TIMEOUT_SECS` (default 600)
+pub fn establish_pool() -> Result<DbPool, dotenvy::Error> {
+    let db_url = dotenvy::var("DATABASE_URL")?;
+    let max_size: u32 = env_or("DATABASE_POOL_MAX_SIZE", 10);
+    let connection_timeout: u64 = env_or("DATABASE_POOL_CONNECTION_TIMEOUT_SECS", 5);
+    let idle_timeout: u64 = env_or("DATABASE_POOL_IDLE_TIMEOUT_SECS", 600);
+
+    Ok(Pool::builder()
+        .max_size(max_size.max(1))
+        .min_idle(Some(0))
+        .connection_timeout(Duration::from_secs(connection_timeout.max(1)))
+        .idle_timeout(Some(Duration::from_secs(idle_timeout)))
+        .build_unchecked(ConnectionManager::<PgConnection>::new(db_url)))
+}
+
+/// Checks out a connection without blocking the async runtime while the pool waits for one
+pub async fn get_connection(pool: &DbPool) -> Result<DbConnection, DatabaseUnavailable> {
+    let pool = pool.clone();
+    match tokio::task::spawn_blocking(move || pool.get()).await {
+        Ok(Ok(conn)) => Ok(conn),
+        Ok(Err(e)) => Err(DatabaseUnavailable(e.to_string())),
+        Err(e) => Err(DatabaseUnavailable(e.to_string())),
+    }
+}
+
+/// Returned as a 503 when no database connection could be checked out of the pool
+#[derive(Debug)]
+pub struct DatabaseUnavailable(pub String);
+
+impl IntoResponse for DatabaseUnavailable {
+    fn into_response(self) -> Response {
+        eprintln!("Database unavailable: {}", self.0);
+        (StatusCode::SERVICE_UNAVAILABLE, Json(ErrorResponse { error: "Database is currently unavailable, try again later".to_string() })).into_response()
+    }
+}
+
+/// Extractor for handlers that need a database connection
+pub struct DbConn(pub DbConnection);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for DbConn
+where
+    DbPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = DatabaseUnavailable;
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let pool = DbPool::from_ref(state);
+        Ok(DbConn(get_connection(&pool).await?))
+    }
 }
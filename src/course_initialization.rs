@@ -1,16 +1,24 @@
-//! By default, when the backend is initialized, the courses table is empty.
-//! This file is used to initialize the courses table with the data from the courses.json file.
-use diesel::prelude::*;
-use serde::Deserialize;
+//! Keeps the courses table in sync with the catalog in the courses.json file.
+//! The sync runs when the backend starts and can be triggered by admins at `POST /v1/admin/courses/sync`.
+//! It's idempotent: new courses are added, renamed or moved courses are updated, and courses that disappeared
+//! from the file are marked inactive rather than deleted since resources and links still reference them.
+//! Courses edited through the admin API keep the admin's name and faculty, the sync only reactivates them.
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::BufReader;
+
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use serde::{Deserialize, Serialize};
 use serde_json::from_reader;
-use crate::connection::establish_connection;
+
+use crate::faculties::Faculties;
+use crate::models::Course;
 use crate::schema::courses;
 
 #[derive(Deserialize)]
-struct CourseJson {
-    faculty: i16,
+pub struct CourseJson {
+    faculty: Faculties,
     id: String,
     name: String
 }
@@ -20,36 +28,176 @@ struct CourseJson {
 struct NewCourse {
     course_id: String,
     course_name: String,
-    course_faculty: i16,
+    course_faculty: Faculties,
+    is_active: bool,
 }
 
-pub async fn initialize_courses_if_empty() -> Result<(), Box<dyn std::error::Error>> {
-    let conn = &mut establish_connection()?;
-
-    // Check if courses table is empty
-    use crate::schema::courses::dsl::*;
-    let count: i64 = courses.count().get_result(conn)?;
-    
-    if count != 0 {
-        return Ok(());
-    }
+/// Ids of the courses a sync touched
+#[derive(Serialize, Default)]
+pub struct CourseSyncReport {
+    pub added: Vec<String>,
+    /// Renamed, moved to another faculty, or back in the catalog after being removed
+    pub changed: Vec<String>,
+    /// Marked inactive
+    pub removed: Vec<String>,
+    /// Edited by an admin and listed differently in the catalog, left as the admin set them
+    pub kept_edits: Vec<String>,
+}
 
+/// Reads the catalog at `COURSES_JSON_PATH`
+pub fn read_courses_json() -> Result<Vec<CourseJson>, Box<dyn std::error::Error>> {
     let json_file_path = dotenvy::var("COURSES_JSON_PATH")?;
     let file = File::open(json_file_path)?;
     let reader = BufReader::new(file);
     let courses_data: Vec<CourseJson> = from_reader(reader)?;
 
-    for course_json in courses_data {
-        let new_course = NewCourse {
-            course_id: course_json.id,
-            course_name: course_json.name,
-            course_faculty: course_json.faculty
-        };
-        
-        diesel::insert_into(courses)
-            .values(&new_course)
+    // An empty catalog is much more likely a broken file than every course being dropped
+    if courses_data.is_empty() {
+        return Err("The courses JSON file has no courses".into());
+    }
+
+    Ok(courses_data)
+}
+
+/// Adds, updates and deactivates courses so the table matches `courses_data`, in one transaction
+pub fn sync_courses(conn: &mut PgConnection, courses_data: Vec<CourseJson>) -> Result<CourseSyncReport, diesel::result::Error> {
+    // Keyed by id so a course listed twice is only synced once, the last entry wins
+    let catalog: BTreeMap<String, CourseJson> = courses_data.into_iter()
+        .map(|course| (course.id.trim().to_string(), course))
+        .filter(|(id, _)| !id.is_empty())
+        .collect();
+
+    conn.transaction(|conn| {
+        let existing: HashMap<String, (Course, bool)> = courses::table
+            .select((Course::as_select(), courses::edited_by_admin))
+            .for_update()
+            .load::<(Course, bool)>(conn)?
+            .into_iter()
+            .map(|(course, edited_by_admin)| (course.course_id.clone(), (course, edited_by_admin)))
+            .collect();
+
+        let mut report = CourseSyncReport::default();
+        let mut upserts: Vec<NewCourse> = Vec::new();
+        let mut reactivated: Vec<String> = Vec::new();
+        for (id, course) in catalog.iter() {
+            let differs = |current: &Course| current.course_name != course.name || current.course_faculty != course.faculty;
+            match existing.get(id) {
+                None => report.added.push(id.clone()),
+                Some((current, true)) => {
+                    if differs(current) {
+                        report.kept_edits.push(id.clone());
+                    }
+                    if !current.is_active {
+                        report.changed.push(id.clone());
+                        reactivated.push(id.clone());
+                    }
+                    continue;
+                },
+                Some((current, false)) if differs(current) || !current.is_active => report.changed.push(id.clone()),
+                Some(_) => continue
+            }
+
+            upserts.push(NewCourse { course_id: id.clone(), course_name: course.name.clone(), course_faculty: course.faculty, is_active: true });
+        }
+
+        // Postgres caps the number of bind parameters in a statement
+        for chunk in upserts.chunks(1000) {
+            diesel::insert_into(courses::table)
+                .values(chunk)
+                .on_conflict(courses::course_id)
+                .do_update()
+                .set((
+                    courses::course_name.eq(excluded(courses::course_name)),
+                    courses::course_faculty.eq(excluded(courses::course_faculty)),
+                    courses::is_active.eq(true)
+                ))
+                .execute(conn)?;
+        }
+
+        diesel::update(courses::table.filter(courses::course_id.eq_any(&reactivated)))
+            .set(courses::is_active.eq(true))
+            .execute(conn)?;
+
+        report.removed = existing.values()
+            .filter(|(course, _)| course.is_active && !catalog.contains_key(&course.course_id))
+            .map(|(course, _)| course.course_id.clone())
+            .collect();
+        report.removed.sort();
+        diesel::update(courses::table.filter(courses::course_id.eq_any(&report.removed)))
+            .set(courses::is_active.eq(false))
             .execute(conn)?;
+
+        Ok(report)
+    })
+}
+
+/// Syncs the courses table with the file at `COURSES_JSON_PATH`
+pub fn sync_courses_from_json(conn: &mut PgConnection) -> Result<CourseSyncReport, Box<dyn std::error::Error>> {
+    let courses_data = read_courses_json()?;
+    Ok(sync_courses(conn, courses_data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_connection;
+
+    fn catalog(courses: &[(&str, &str)]) -> Vec<CourseJson> {
+        let courses: Vec<serde_json::Value> = courses.iter().map(|(id, name)| serde_json::json!({ "faculty": 0, "id": id, "name": name })).collect();
+        serde_json::from_value(serde_json::Value::Array(courses)).unwrap()
+    }
+
+    fn get_course(conn: &mut PgConnection, course_id: &str) -> Course {
+        courses::table.find(course_id).select(Course::as_select()).first(conn).unwrap()
+    }
+
+    #[test]
+    fn syncs_courses_with_the_catalog() {
+        let Some(mut conn) = test_connection() else { return };
+        // Made up ids so the courses already in the database are the only other ones, and all get removed by the first sync
+        let [a, b, c] = ["SYNCTEST1", "SYNCTEST2", "SYNCTEST3"];
+
+        let report = sync_courses(&mut conn, catalog(&[(a, "Course A"), (b, "Course B")])).unwrap();
+        assert_eq!(report.added, [a, b]);
+        assert!(report.changed.is_empty());
+
+        let report = sync_courses(&mut conn, catalog(&[(b, "Course B"), (a, "Course A")])).unwrap();
+        assert!(report.added.is_empty() && report.changed.is_empty() && report.removed.is_empty() && report.kept_edits.is_empty());
+
+        let report = sync_courses(&mut conn, catalog(&[(a, "Course A Renamed"), (c, "Course C")])).unwrap();
+        assert_eq!(report.added, [c]);
+        assert_eq!(report.changed, [a]);
+        assert_eq!(report.removed, [b]);
+        assert_eq!(get_course(&mut conn, a).course_name, "Course A Renamed");
+        assert!(!get_course(&mut conn, b).is_active);
+
+        let report = sync_courses(&mut conn, catalog(&[(a, "Course A Renamed"), (b, "Course B"), (c, "Course C")])).unwrap();
+        assert_eq!(report.changed, [b]);
+        assert!(get_course(&mut conn, b).is_active);
     }
 
-    Ok(())
-} 
\ No newline at end of file
+    #[test]
+    fn keeps_admin_edits() {
+        let Some(mut conn) = test_connection() else { return };
+        let course_id = "SYNCTEST4";
+        sync_courses(&mut conn, catalog(&[(course_id, "Catalog Name")])).unwrap();
+        diesel::update(courses::table.find(course_id))
+            .set((courses::course_name.eq("Admin Name"), courses::edited_by_admin.eq(true)))
+            .execute(&mut conn)
+            .unwrap();
+
+        let report = sync_courses(&mut conn, catalog(&[(course_id, "Catalog Name")])).unwrap();
+        assert!(report.changed.is_empty());
+        assert_eq!(report.kept_edits, [course_id]);
+        assert_eq!(get_course(&mut conn, course_id).course_name, "Admin Name");
+
+        // Removing and bringing the course back reactivates it without undoing the edit
+        sync_courses(&mut conn, catalog(&[("SYNCTEST5", "Other Course")])).unwrap();
+        assert!(!get_course(&mut conn, course_id).is_active);
+        let report = sync_courses(&mut conn, catalog(&[(course_id, "Catalog Name")])).unwrap();
+        assert_eq!(report.changed, [course_id]);
+        let course = get_course(&mut conn, course_id);
+        assert!(course.is_active);
+        assert_eq!(course.course_name, "Admin Name");
+    }
+}
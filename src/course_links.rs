@@ -0,0 +1,218 @@
+//! Links attached to courses, like playlists or drive folders.
+//! Uploaders can edit and delete their links (moderators use the audited `/v1/admin` routes), and a course can't have the same URL twice.
+//! URLs are compared after normalizing them, so `http://www.example.com/a/?utm_source=x` and `https://example.com/a` are the same link.
+use axum::{extract::Path, http::StatusCode, response::{IntoResponse, Response}, Json};
+use diesel::prelude::*;
+use url::{form_urlencoded, Url};
+use uuid::Uuid;
+
+use crate::accounts::AuthenticatedUser;
+use crate::connection::DbConn;
+use crate::link_kinds::LinkKind;
+use crate::link_validation::validate_link_url;
+use crate::course_retreival::delete_course_link_from_db;
+use crate::models::{error_response, CourseLinkChangeset, CourseResourceLink, EditCourseLink, User};
+use crate::schema::{course_resource_links, courses};
+
+/// Query parameters that only track where a link was shared from
+const TRACKING_PARAMS: [&str; 6] = ["fbclid", "gclid", "igshid", "mc_cid", "mc_eid", "si"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum CourseLinkError {
+    #[error("This link was already added to the course")]
+    Duplicate,
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error)
+}
+
+fn is_tracking_param(name: &str) -> bool {
+    name.starts_with("utm_") || TRACKING_PARAMS.contains(&name)
+}
+
+/// The form of a URL used to find duplicates. Drops the scheme, `www.`, trailing slashes and tracking parameters,
+/// "https://www.youtube.com/playlist/?list=abc&utm_source=x" becomes "youtube.com/playlist?list=abc"
+pub fn normalize_link_url(url: &str) -> String {
+    let Ok(parsed) = Url::parse(url.trim()) else {
+        return url.trim().to_string();
+    };
+
+    let mut normalized = parsed.host_str().unwrap_or_default().trim_start_matches("www.").to_string();
+    if let Some(port) = parsed.port() {
+        normalized.push_str(&format!(":{}", port));
+    }
+    normalized.push_str(parsed.path().trim_end_matches('/'));
+
+    let params: Vec<(String, String)> = parsed.query_pairs()
+        .filter(|(name, _)| !is_tracking_param(name))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if !params.is_empty() {
+        normalized.push('?');
+        normalized.push_str(&form_urlencoded::Serializer::new(String::new()).extend_pairs(params).finish());
+    }
+
+    if let Some(fragment) = parsed.fragment().filter(|fragment| !fragment.is_empty()) {
+        normalized.push('#');
+        normalized.push_str(fragment);
+    }
+
+    normalized
+}
+
+/// Trims the title of a link, which can't be empty
+pub fn validate_link_title(title: &str) -> Result<String, String> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err("Title can't be empty".to_string());
+    }
+
+    Ok(title.to_string())
+}
+
+/// Locks the course row so concurrent requests can't both add the same link, `NotFound` if there's no such course
+pub fn lock_course_links(conn: &mut PgConnection, course_id: &str) -> Result<(), diesel::result::Error> {
+    courses::table.find(course_id).select(courses::course_id).for_update().first::<String>(conn)?;
+    Ok(())
+}
+
+/// Fails with `Duplicate` if another link of the course has the same normalized URL
+pub fn check_link_not_duplicate(conn: &mut PgConnection, course_id: &str, url: &str, except_link_id: Option<Uuid>) -> Result<(), CourseLinkError> {
+    let normalized = normalize_link_url(url);
+    let existing = course_resource_links::table
+        .filter(course_resource_links::course_id.eq(course_id))
+        .select((course_resource_links::link_id, course_resource_links::link_url))
+        .load::<(Uuid, String)>(conn)?;
+
+    if existing.iter().any(|(link_id, link_url)| Some(*link_id) != except_link_id && normalize_link_url(link_url) == normalized) {
+        return Err(CourseLinkError::Duplicate);
+    }
+
+    Ok(())
+}
+
+fn update_course_link_in_db(conn: &mut PgConnection, link: &CourseResourceLink, changes: CourseLinkChangeset) -> Result<CourseResourceLink, CourseLinkError> {
+    conn.transaction(|conn| {
+        if let Some(url) = &changes.link_url {
+            lock_course_links(conn, &link.course_id)?;
+            check_link_not_duplicate(conn, &link.course_id, url, Some(link.link_id))?;
+        }
+
+        Ok(diesel::update(course_resource_links::table.find(link.link_id))
+            .set(changes)
+            .returning(CourseResourceLink::as_returning())
+            .get_result(conn)?)
+    })
+}
+
+fn find_owned_course_link(conn: &mut PgConnection, link_id: Uuid, user: &User) -> Result<CourseResourceLink, Response> {
+    let link = match course_resource_links::table.find(link_id).first::<CourseResourceLink>(conn) {
+        Ok(link) => link,
+        Err(diesel::result::Error::NotFound) => return Err(error_response(StatusCode::NOT_FOUND, format!("Link with id {} not found", link_id))),
+        Err(e) => return Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    };
+
+    if link.uploader_id != Some(user.user_id) {
+        return Err(error_response(StatusCode::FORBIDDEN, "Only the uploader can change this link"));
+    }
+
+    Ok(link)
+}
+
+pub fn course_link_error_response(e: CourseLinkError) -> Response {
+    match e {
+        CourseLinkError::Duplicate => error_response(StatusCode::CONFLICT, e.to_string()),
+        CourseLinkError::Database(diesel::result::Error::NotFound) => error_response(StatusCode::NOT_FOUND, "Course not found"),
+        CourseLinkError::Database(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+pub async fn edit_course_link(AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Path(link_id): Path<Uuid>, Json(payload): Json<EditCourseLink>) -> Result<impl IntoResponse, StatusCode> {
+    if payload.title.is_none() && payload.url.is_none() {
+        return Ok(error_response(StatusCode::BAD_REQUEST, "Nothing to update"));
+    }
+
+    let title = match payload.title.as_deref().map(validate_link_title) {
+        Some(Ok(title)) => Some(title),
+        Some(Err(e)) => return Ok(error_response(StatusCode::BAD_REQUEST, e)),
+        None => None
+    };
+
+    let url = match payload.url.as_deref().map(validate_link_url) {
+        Some(Ok(url)) => Some(url),
+        Some(Err(e)) => return Ok(error_response(StatusCode::BAD_REQUEST, e)),
+        None => None
+    };
+
+    let link = match find_owned_course_link(&mut conn, link_id, &user) {
+        Ok(link) => link,
+        Err(response) => return Ok(response)
+    };
+
+    let mut changes = CourseLinkChangeset { link_kind: url.as_deref().map(LinkKind::from_url), link_title: title, ..Default::default() };
+    if let Some(url) = url {
+        changes.link_url = Some(url);
+        changes.last_checked = Some(None);
+        changes.last_status = Some(None);
+        changes.consecutive_failures = Some(0);
+    }
+
+    match update_course_link_in_db(&mut conn, &link, changes) {
+        Ok(link) => Ok(Json(link).into_response()),
+        Err(e) => Ok(course_link_error_response(e))
+    }
+}
+
+pub async fn delete_course_link(AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Path(link_id): Path<Uuid>) -> Result<impl IntoResponse, StatusCode> {
+    if let Err(response) = find_owned_course_link(&mut conn, link_id, &user) {
+        return Ok(response);
+    }
+
+    match delete_course_link_from_db(&mut conn, link_id) {
+        Ok(_) => Ok(StatusCode::OK.into_response()),
+        Err(diesel::result::Error::NotFound) => Ok(error_response(StatusCode::NOT_FOUND, format!("Link with id {} not found", link_id))),
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_equivalent_urls_the_same() {
+        let cases = [
+            ("http://example.com/notes", "https://example.com/notes"),
+            ("https://www.example.com/notes", "https://example.com/notes"),
+            ("https://example.com/notes/", "https://example.com/notes"),
+            ("https://EXAMPLE.com/notes", "https://example.com/notes"),
+            ("https://example.com:443/notes", "https://example.com/notes"),
+            ("http://example.com:80/notes", "https://example.com/notes"),
+            ("https://example.com/notes?utm_source=x&fbclid=y&si=z", "https://example.com/notes"),
+            ("https://youtube.com/playlist?utm_medium=share&list=abc", "https://www.youtube.com/playlist/?list=abc"),
+            ("https://example.com/notes#", "https://example.com/notes"),
+        ];
+        for (url, same_as) in cases {
+            assert_eq!(normalize_link_url(url), normalize_link_url(same_as), "{} vs {}", url, same_as);
+        }
+    }
+
+    #[test]
+    fn keeps_what_makes_urls_different() {
+        assert_eq!(normalize_link_url("https://www.youtube.com/playlist/?list=abc&utm_source=x"), "youtube.com/playlist?list=abc");
+        assert_ne!(normalize_link_url("https://example.com/notes?page=1"), normalize_link_url("https://example.com/notes?page=2"));
+        assert_ne!(normalize_link_url("https://example.com:8080/notes"), normalize_link_url("https://example.com/notes"));
+        assert_ne!(normalize_link_url("https://example.com/notes#part-2"), normalize_link_url("https://example.com/notes"));
+        assert_ne!(normalize_link_url("https://example.com/Notes"), normalize_link_url("https://example.com/notes"));
+    }
+
+    #[test]
+    fn leaves_unparsable_urls_trimmed() {
+        assert_eq!(normalize_link_url("  not a url "), "not a url");
+    }
+
+    #[test]
+    fn rejects_empty_titles() {
+        assert_eq!(validate_link_title("  Lecture playlist "), Ok("Lecture playlist".to_string()));
+        assert!(validate_link_title(" \t ").is_err());
+    }
+}
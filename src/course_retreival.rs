@@ -1,223 +1,354 @@
-use diesel::dsl::count_star;
+use diesel::dsl::{count_star, sql};
+use diesel::expression::{BoxableExpression, SqlLiteral};
+use diesel::sql_types::{BigInt, Bool, Double};
 use diesel::{BoolExpressionMethods, ExpressionMethods, PgTextExpressionMethods, SelectableHelper};
-use diesel::{PgConnection, QueryDsl, RunQueryDsl};
+use diesel::pg::Pg;
+use diesel::{Connection, PgConnection, QueryDsl, RunQueryDsl};
 use uuid::Uuid;
-use crate::authentication::get_token_cache;
 use crate::schema::{self, course_resource_links, course_resources};
-use crate::models::{CourseDetails, CourseDetailsLinkResponse, CourseDetailsResourceResponse, CourseResource, CourseResourceFile, CourseResourceLink, GetCoursesResponse};
-use reqwest::Client;
-
-/* Don't make this public. it's what the gcloud api returns when you upload a file */
-#[derive(Debug, serde::Deserialize)]
-struct __UploadResourceFileResponse {
-    pub mediaLink: String
-}
-
-pub struct CourseResourceUploadFile {
-    pub filename: String,
-    pub data: Vec<u8>
-}
-
-pub fn get_course_details_from_db(conn: &mut PgConnection, course_id: String, resource_type: i16) -> Result<CourseDetails, diesel::result::Error> {
+use crate::models::{Course, CourseDetails, CourseDetailsLinkResponse, CourseDetailsResourceResponse, CourseMetadata, CourseResource, CourseResourceChangeset, CourseResourceFile, CourseResourceFilters, CourseResourceLink, CourseSort, CourseCursor, CourseCursorKey, CoursesPage, GetCoursesResponse, InsertCourseResource, ResourceSort};
+use crate::comments::get_comment_counts_from_db;
+use crate::course_links::{check_link_not_duplicate, lock_course_links, CourseLinkError};
+use crate::course_search::{escape_like, CourseSearch};
+use crate::faculties::Faculties;
+use crate::link_kinds::LinkKind;
+use crate::resource_types::ResourceType;
+use crate::ratings::{get_course_rating_summaries_from_db, get_course_rating_summary_from_db};
+use crate::resource_uploads::delete_resource_files_from_storage;
+use crate::storage::StorageBackend;
+
+pub fn get_course_details_from_db(conn: &mut PgConnection, course_id: String, resource_type: ResourceType, filters: &CourseResourceFilters, link_kind: Option<LinkKind>) -> Result<CourseDetails, diesel::result::Error> {
     use schema::courses;
     let query = courses::table.filter(courses::course_id.eq(course_id.to_uppercase()));
 
     use schema::course_resources;
-    let course_resources_query = course_resources::table.filter(course_resources::course_id.eq(course_id.to_uppercase()));
-    let no_notes = course_resources_query.clone().filter(course_resources::resource_type.eq(0)).select(count_star()).get_result::<i64>(conn)?;
-    let no_exams = course_resources_query.filter(course_resources::resource_type.eq(1)).select(count_star()).get_result::<i64>(conn)?;
-
-    if let Ok(course) = query.first(conn) {
-        return Ok(CourseDetails { metadata: course, resources: get_course_resources_from_db(conn, course_id.clone(), resource_type)?, links: get_course_links_from_db(conn, course_id)?, no_notes, no_exams });
+    let course_resources_query = course_resources::table.filter(course_resources::course_id.eq(course_id.to_uppercase()).and(course_resources::is_hidden.eq(false)));
+    let no_notes = course_resources_query.clone().filter(course_resources::resource_type.eq(ResourceType::Notes)).select(count_star()).get_result::<i64>(conn)?;
+    let no_exams = course_resources_query.filter(course_resources::resource_type.eq(ResourceType::Exams)).select(count_star()).get_result::<i64>(conn)?;
+
+    if let Ok(course) = query.select(Course::as_select()).first(conn) {
+        let ratings = get_course_rating_summary_from_db(conn, &course.course_id)?;
+        let (resources, total_resources) = get_course_resources_from_db(conn, course_id.clone(), resource_type, filters)?;
+        return Ok(CourseDetails { metadata: CourseMetadata { course, ratings }, resources, links: get_course_links_from_db(conn, course_id, link_kind)?, total_resources, no_notes, no_exams });
     }
     return Err(diesel::result::Error::NotFound);
 }
 
-fn get_course_links_from_db(conn: &mut PgConnection, course_id: String) -> Result<Vec<CourseDetailsLinkResponse>, diesel::result::Error> { 
+fn get_course_links_from_db(conn: &mut PgConnection, course_id: String, link_kind: Option<LinkKind>) -> Result<Vec<CourseDetailsLinkResponse>, diesel::result::Error> { 
     use schema::course_resource_links;
-    let query = course_resource_links::table.filter(course_resource_links::course_id.eq(course_id.to_uppercase()));
+    let mut query = course_resource_links::table.filter(course_resource_links::course_id.eq(course_id.to_uppercase()).and(course_resource_links::is_hidden.eq(false))).into_boxed();
+    if let Some(link_kind) = link_kind {
+        query = query.filter(course_resource_links::link_kind.eq(link_kind));
+    }
     let links_from_db = query.load::<CourseResourceLink>(conn)?;
     // We load a CourseResourceLink and then return a CourseDetailsLinkResponse
     // but why??
-    // well because I don't wanna return useless info with each link like the courseID and linkID
+    // well because I don't wanna return useless info with each link like the courseID
     // like u already know the courseID if ur requesting the link for the course here...
+    // the linkID is needed though, to edit or delete the link
     let mut links_to_return: Vec<CourseDetailsLinkResponse> = Vec::new();
     for link in links_from_db { 
-        links_to_return.push(CourseDetailsLinkResponse { title: link.link_title, url: link.link_url });
+        links_to_return.push(CourseDetailsLinkResponse::from(link));
     }
 
     return Ok(links_to_return);
 }
 
-pub fn insert_course_link_into_db(conn: &mut PgConnection, link_title: String, link_url: String, course_id: String) -> Result<CourseResourceLink, diesel::result::Error> { 
+/// Inserts a link, unless the same URL was already added to the course
+pub fn insert_course_link_into_db(conn: &mut PgConnection, link_title: String, link_url: String, course_id: String, uploader_id: Option<Uuid>) -> Result<CourseResourceLink, CourseLinkError> { 
     let link_uuid = Uuid::new_v4();
     let db_resource_to_insert = CourseResourceLink { 
         course_id,
         link_id: link_uuid,
+        link_kind: LinkKind::from_url(&link_url),
         link_title,
-        link_url
+        link_url,
+        uploader_id,
+        is_hidden: false,
+        date_added: chrono::Utc::now(),
+        last_checked: None,
+        last_status: None,
+        consecutive_failures: 0
     };
     
-    diesel::insert_into(course_resource_links::table)
-        .values(db_resource_to_insert)
-        .get_result::<CourseResourceLink>(conn)
+    conn.transaction(|conn| {
+        lock_course_links(conn, &db_resource_to_insert.course_id)?;
+        check_link_not_duplicate(conn, &db_resource_to_insert.course_id, &db_resource_to_insert.link_url, None)?;
+
+        Ok(diesel::insert_into(course_resource_links::table)
+            .values(db_resource_to_insert)
+            .get_result::<CourseResourceLink>(conn)?)
+    })
 }
 
-fn get_course_resources_from_db(conn: &mut PgConnection, course_id: String, resource_type: i16) -> Result<Vec<CourseDetailsResourceResponse>, diesel::result::Error> {
-    use schema::course_resources;
-    let query = course_resources::table.filter(course_resources::course_id.eq(course_id.to_uppercase()).and(course_resources::resource_type.eq(resource_type)));
+pub fn delete_course_link_from_db(conn: &mut PgConnection, link_id: Uuid) -> Result<CourseResourceLink, diesel::result::Error> {
+    diesel::delete(course_resource_links::table.find(link_id))
+        .returning(CourseResourceLink::as_returning())
+        .get_result(conn)
+}
+
+fn resources_per_page() -> i64 {
+    return 20;
+}
+
+// The visible resources of a course matching the filters, boxed so it can be built once for both the count and the page
+fn filtered_course_resources_query<'a>(course_id: &str, resource_type: ResourceType, filters: &'a CourseResourceFilters) -> course_resources::BoxedQuery<'a, Pg> {
+    let mut query = course_resources::table.filter(
+        course_resources::course_id.eq(course_id.to_uppercase())
+        .and(course_resources::resource_type.eq(resource_type))
+        .and(course_resources::is_hidden.eq(false))
+    ).into_boxed();
+
+    if let Some(semester) = &filters.semester {
+        query = query.filter(course_resources::semester.eq(semester));
+    }
+
+    if let Some(from) = filters.academic_year_from {
+        query = query.filter(course_resources::academic_year.ge(from));
+    }
+
+    if let Some(to) = filters.academic_year_to {
+        query = query.filter(course_resources::academic_year.le(to));
+    }
+
+    if let Some(issolved) = filters.issolved {
+        query = query.filter(course_resources::issolved.eq(issolved));
+    }
+
+    if let Some(q) = &filters.q {
+        let formatted_string = format!("%{}%", escape_like(q));
+        query = query.filter(course_resources::title.ilike(formatted_string.clone()).or(course_resources::subtitle.ilike(formatted_string)));
+    }
+
+    query
+}
+
+/// Returns a page of the resources matching the filters, along with how many match in total
+fn get_course_resources_from_db(conn: &mut PgConnection, course_id: String, resource_type: ResourceType, filters: &CourseResourceFilters) -> Result<(Vec<CourseDetailsResourceResponse>, i64), diesel::result::Error> {
+    let total_resources = filtered_course_resources_query(&course_id, resource_type, filters).select(count_star()).get_result::<i64>(conn)?;
+
+    let limit = resources_per_page();
+    let query = filtered_course_resources_query(&course_id, resource_type, filters)
+        .limit(limit)
+        .offset((sanitize_page_input(filters.page) - 1) * limit);
+
+    // Ties go to the newest resource
+    let query = match filters.sort {
+        ResourceSort::Score => query.order((course_resources::score.desc(), course_resources::dateuploaded.desc())),
+        ResourceSort::Newest => query.order(course_resources::dateuploaded.desc()),
+        ResourceSort::AcademicYear => query.order((course_resources::academic_year.desc(), course_resources::dateuploaded.desc())),
+        ResourceSort::Title => query.order((course_resources::title.asc(), course_resources::dateuploaded.desc()))
+    };
     if let Ok(resources) = query.load::<CourseResource>(conn) {
+        let resource_ids: Vec<Uuid> = resources.iter().map(|resource| resource.resource_id).collect();
+        let comment_counts = get_comment_counts_from_db(conn, &resource_ids)?;
+
         let mut resources_with_files: Vec<CourseDetailsResourceResponse> = Vec::new();
         for resource in resources {
-            if let Ok(files) = get_course_resource_files_from_db(conn, resource.resource_id) {
-                resources_with_files.push(CourseDetailsResourceResponse { resource_info: resource, files });
+            if let Ok(files) = get_course_resource_files_from_db(conn, resource.resource_id, false) {
+                let comment_count = comment_counts.get(&resource.resource_id).copied().unwrap_or(0);
+                resources_with_files.push(CourseDetailsResourceResponse { resource_info: resource, files, comment_count });
             }
         }
 
-        return Ok(resources_with_files);
+        return Ok((resources_with_files, total_resources));
     }
     return Err(diesel::result::Error::NotFound);
 }
 
-fn get_course_resource_files_from_db(conn: &mut PgConnection, resource_id: Uuid) -> Result<Vec<CourseResourceFile>, diesel::result::Error> {
+fn get_course_resource_files_from_db(conn: &mut PgConnection, resource_id: Uuid, include_hidden: bool) -> Result<Vec<CourseResourceFile>, diesel::result::Error> {
     use schema::course_resource_files;
-    let query = course_resource_files::table.filter(course_resource_files::resource_id.eq(resource_id));
+    let mut query = course_resource_files::table.filter(course_resource_files::resource_id.eq(resource_id)).into_boxed();
+    if !include_hidden {
+        query = query.filter(course_resource_files::is_hidden.eq(false));
+    }
     if let Ok(files) = query.load(conn) {
         return Ok(files);
     }
     return Err(diesel::result::Error::NotFound);
 }
 
-fn sanitize_file_name_to_upload(file_name: String) -> String {
-    return file_name.replace(" ", "_")
-    .replace("#", "_")
-    .replace("'", "_")
-    .replace("\"", "_")
-    .replace(":", "_")
-    .replace(";", "_")
-    .replace("|", "_");
+/// Inserts a resource whose files have already been uploaded.
+/// Both rows go in together or not at all, and if the insert fails the uploaded objects are deleted again
+pub async fn insert_course_resource_into_db(conn: &mut PgConnection, storage: &dyn StorageBackend, resource_id: Uuid, course_id: String, payload: InsertCourseResource, files: Vec<CourseResourceFile>, uploader_id: Option<Uuid>) -> Result<CourseResource, diesel::result::Error> {
+    let new_resource = CourseResource {
+        title: payload.title,
+        subtitle: payload.subtitle,
+        resource_id,
+        course_id: course_id.clone(),
+        resource_type: payload.resource_type,
+        dateuploaded: chrono::Utc::now(),
+        semester: payload.semester,
+        academic_year: payload.academic_year,
+        issolved: payload.issolved,
+        uploader_id,
+        is_hidden: false,
+        score: 0
+    };
+
+    let inserted = conn.transaction(|conn| {
+        let resource = diesel::insert_into(course_resources::table)
+            .values(new_resource)
+            .returning(CourseResource::as_returning())
+            .get_result(conn)?;
+
+        diesel::insert_into(schema::course_resource_files::table)
+            .values(&files)
+            .execute(conn)?;
+
+        Ok::<CourseResource, diesel::result::Error>(resource)
+    });
+
+    if inserted.is_err() {
+        delete_resource_files_from_storage(storage, &course_id, &files).await;
+    }
+
+    inserted
 }
 
-fn file_content_type(file_name: String) -> String {
-    let extension = file_name.split('.').last().unwrap();
-    return match extension {
-        "pdf" => "application/pdf".to_string(),
-        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document".to_string(),
-        "pptx" => "application/vnd.ms-powerpoint".to_string(),
-        "ppt" => "application/vnd.ms-powerpoint".to_string(),
-        "jpg" => "image/jpeg".to_string(),
-        "jpeg" => "image/jpeg".to_string(),
-        "png" => "image/png".to_string(),
-        "gif" => "image/gif".to_string(),
-        _ => "application/octet-stream".to_string()
-    };
+pub fn update_course_resource_in_db(conn: &mut PgConnection, resource_id: Uuid, changes: CourseResourceChangeset) -> Result<CourseResource, diesel::result::Error> {
+    diesel::update(course_resources::table.find(resource_id))
+        .set(changes)
+        .returning(CourseResource::as_returning())
+        .get_result(conn)
 }
 
-pub async fn insert_course_resource_into_db(conn: &mut PgConnection, title: String, subtitle: Option<String>, course_id: String, resource_type: i16, semester: String, academic_year: i32, is_solved: bool, files: Vec<CourseResourceUploadFile>) -> Result<CourseResource, diesel::result::Error> {
-    let new_resource_id = Uuid::new_v4();
-    let bucket_folder_name = format!("course_resources/{}/{}", course_id, new_resource_id);
-    // concurrently upload files to bucket
-    let bucket_name = "gjufilesresources";
-    let mut new_resource_files: Vec<CourseResourceFile> = Vec::new();
-    for file in files {
-        let sanitized_file_name = sanitize_file_name_to_upload(file.filename);
-        let file_in_bucket_name = format!("{}/{}", bucket_folder_name, sanitized_file_name);
-        let request_url = format!(
-            "https://storage.googleapis.com/upload/storage/v1/b/{}/o?uploadType=media&name={}",
-            bucket_name, file_in_bucket_name
-        );
-
-        let content_type = file_content_type(sanitized_file_name.clone());
-        println!("Content type: {}", content_type);
-        let response = Client::new()
-            .post(&request_url)
-            .bearer_auth(get_token_cache().await.unwrap())
-            .body(file.data)
-            .header("Content-Type", content_type)
-            .send()
-            .await
-            .unwrap();
-        // Check that the response is a 200-299 status code 
-        // to make sure the file uploaded successfully
-        if !(200..=299).contains(&response.status().as_u16()) {
-            return Err(diesel::result::Error::NotFound);
-        }
-        
-        let file_url = format!("https://storage.googleapis.com/{}/{}", bucket_name, file_in_bucket_name);
-        let file_id = Uuid::new_v4();
-
-        let new_resource_file = CourseResourceFile {
-            file_id,
-            file_name: sanitized_file_name,
-            file_url,
-            resource_id: new_resource_id
-        };
-
-        new_resource_files.push(new_resource_file);
+/// Deletes a resource along with its file rows, returning them so their objects can be removed from storage
+pub fn delete_course_resource_from_db(conn: &mut PgConnection, resource_id: Uuid) -> Result<(CourseResource, Vec<CourseResourceFile>), diesel::result::Error> {
+    use schema::course_resource_files;
+    conn.transaction(|conn| {
+        let files = diesel::delete(course_resource_files::table.filter(course_resource_files::resource_id.eq(resource_id)))
+            .returning(CourseResourceFile::as_returning())
+            .get_results(conn)?;
+
+        let resource = diesel::delete(course_resources::table.find(resource_id))
+            .returning(CourseResource::as_returning())
+            .get_result(conn)?;
+
+        Ok((resource, files))
+    })
+}
+
+pub fn get_course_resource_from_db(conn: &mut PgConnection, resource_id: Uuid) -> Result<CourseResource, diesel::result::Error> {
+    course_resources::table.find(resource_id).first(conn)
+}
+
+/// Includes hidden files, this is for managing the resource rather than showing it
+pub fn get_course_resource_with_files_from_db(conn: &mut PgConnection, resource_id: Uuid) -> Result<CourseDetailsResourceResponse, diesel::result::Error> {
+    let resource = get_course_resource_from_db(conn, resource_id)?;
+    let files = get_course_resource_files_from_db(conn, resource_id, true)?;
+    let comment_count = get_comment_counts_from_db(conn, &[resource_id])?.get(&resource_id).copied().unwrap_or(0);
+    Ok(CourseDetailsResourceResponse { resource_info: resource, files, comment_count })
+}
+
+/// Attaches already uploaded files to an existing resource, deleting the objects again if the insert fails
+pub async fn insert_course_resource_files_into_db(conn: &mut PgConnection, storage: &dyn StorageBackend, course_id: &str, files: Vec<CourseResourceFile>) -> Result<Vec<CourseResourceFile>, diesel::result::Error> {
+    let inserted = diesel::insert_into(schema::course_resource_files::table)
+        .values(&files)
+        .returning(CourseResourceFile::as_returning())
+        .get_results(conn);
+
+    if inserted.is_err() {
+        delete_resource_files_from_storage(storage, course_id, &files).await;
     }
 
-    let new_resource = CourseResource {
-        title,
-        subtitle,
-        resource_id: new_resource_id,
-        course_id,
-        resource_type,
-        dateuploaded: chrono::Utc::now(),
-        semester,
-        academic_year,
-        issolved: is_solved
-    };
+    inserted
+}
 
-    match diesel::insert_into(course_resources::table)
-        .values(new_resource)
-        .returning(CourseResource::as_returning())
-        .get_result(conn) {
-            Ok(resource) => {
-                if let Err(e) = diesel::insert_into(schema::course_resource_files::table)
-                    .values(new_resource_files)
-                    .execute(conn) {
-                    return Err(e);
-                }
-                
-                return Ok(resource);
-            }
-            Err(e) => {
-                return Err(e);
-            }
+#[derive(Debug, thiserror::Error)]
+pub enum DeleteCourseResourceFileError {
+    #[error("A resource needs at least one file, delete the resource instead")]
+    LastFile,
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error)
+}
+
+/// Deletes a single file row, returning it along with the course id of its resource so the object can be removed from storage
+pub fn delete_course_resource_file_from_db(conn: &mut PgConnection, resource_id: Uuid, file_id: Uuid) -> Result<(String, CourseResourceFile), DeleteCourseResourceFileError> {
+    use schema::course_resource_files;
+    conn.transaction(|conn| {
+        // Lock the resource so two concurrent deletes can't both think they aren't removing the last file
+        let resource = course_resources::table.find(resource_id).for_update().first::<CourseResource>(conn)?;
+
+        let file = diesel::delete(course_resource_files::table.filter(course_resource_files::file_id.eq(file_id).and(course_resource_files::resource_id.eq(resource_id))))
+            .returning(CourseResourceFile::as_returning())
+            .get_result(conn)?;
+
+        let remaining_files = course_resource_files::table
+            .filter(course_resource_files::resource_id.eq(resource_id))
+            .select(count_star())
+            .get_result::<i64>(conn)?;
+
+        if remaining_files == 0 {
+            return Err(DeleteCourseResourceFileError::LastFile);
         }
+
+        Ok((resource.course_id, file))
+    })
 }
 
 // Sanitize page number input for getting courses
 // if the number is null or below 0 
 // return 1
 // otherwise return the original
-fn sanitize_page_input(page: Option<i64>) -> i64 {
+pub fn sanitize_page_input(page: Option<i64>) -> i64 {
 
-    if let Some(pageN) = page {
-        if pageN <= 0 {
+    if let Some(page_n) = page {
+        if page_n <= 0 {
             return 1;
         }
 
-        return pageN;
+        return page_n;
     }
 
     return 1;
 }
 
-fn courses_per_page() -> i64 {
+/// Courses per page when the client doesn't ask for a page size
+pub fn courses_per_page() -> i64 {
     return 12;
 }
 
-pub fn get_courses_from_db(conn: &mut PgConnection, faculty: Option<i16>, searchTerm: Option<String>, page: Option<i64>) -> Result<GetCoursesResponse, diesel::result::Error> {
+pub const MAX_COURSES_PER_PAGE: i64 = 50;
+
+// Resources of the course that aren't hidden, for sorting courses by how many they have
+fn resource_count() -> SqlLiteral<BigInt> {
+    sql::<BigInt>("(SELECT count(*) FROM course_resources WHERE course_resources.course_id = courses.course_id AND NOT course_resources.is_hidden)")
+}
+
+type CourseExpression<T> = Box<dyn BoxableExpression<schema::courses::table, Pg, SqlType = T>>;
+
+// Courses after the one the cursor points at, in the order of its sort. Course ids break ties
+fn after_course_cursor(cursor: &CourseCursor, relevance: impl Fn() -> CourseExpression<Double>) -> CourseExpression<Bool> {
     use schema::courses;
-    let limit = courses_per_page();
-
-    let mut query = courses::table.into_boxed();
-    if let Some(search) = searchTerm.clone() {
-        let formatted_string = format!("%{}%", search.clone());
-        query = query.filter(
-            courses::course_id.ilike(formatted_string.clone()).or(courses::course_name.ilike(formatted_string.clone()))
-        );
+    let course_id = cursor.course_id.clone();
+
+    match &cursor.key {
+        CourseCursorKey::CourseId => Box::new(courses::course_id.gt(course_id)),
+        CourseCursorKey::Name(name) => Box::new(courses::course_name.gt(name.clone()).or(courses::course_name.eq(name.clone()).and(courses::course_id.gt(course_id)))),
+        CourseCursorKey::Resources(count) => Box::new(resource_count().lt(*count).or(resource_count().eq(*count).and(courses::course_id.gt(course_id)))),
+        // Relevance is computed the same way on every request, so comparing it exactly is fine
+        CourseCursorKey::Relevance(value) => Box::new(relevance().lt(*value).or(relevance().eq(*value).and(courses::course_id.gt(course_id))))
+    }
+}
+
+pub fn get_courses_from_db(conn: &mut PgConnection, faculty: Option<Faculties>, search_term: Option<String>, page: &CoursesPage) -> Result<GetCoursesResponse, diesel::result::Error> {
+    use schema::courses;
+    let limit = page.page_size;
+
+    // Terms without any letters or digits are ignored
+    let search = search_term.as_deref().and_then(CourseSearch::new);
+    let relevance = || -> CourseExpression<Double> {
+        match &search {
+            Some(search) => search.relevance(),
+            None => Box::new(sql::<Double>("0::float8"))
+        }
+    };
+
+    // Courses removed from the catalog are kept for their resources but not listed
+    let mut query = courses::table.filter(courses::is_active.eq(true)).into_boxed();
+    if let Some(search) = &search {
+        query = query.filter(search.matches());
     }
 
     /* 
@@ -236,20 +367,17 @@ pub fn get_courses_from_db(conn: &mut PgConnection, faculty: Option<i16>, search
     isSolved (Boolean)
     */
 
+
     // TODO: Refactor query and count query to use a macro
     if let Some(fac) = faculty { 
         query = query.filter(courses::course_faculty.eq(fac));
     }
 
     // Create a separate count query with the same conditions, but without the limit (for pagination)
-    let mut count_query = courses::table.into_boxed();
+    let mut count_query = courses::table.filter(courses::is_active.eq(true)).into_boxed();
     
-    if let Some(search) = searchTerm {
-        let formatted_string = format!("%{}%", search);
-        count_query = count_query.filter(
-            courses::course_id.ilike(formatted_string.clone())
-            .or(courses::course_name.ilike(formatted_string))
-        )
+    if let Some(search) = &search {
+        count_query = count_query.filter(search.matches());
     }
 
     if let Some(fac) = faculty {
@@ -259,12 +387,116 @@ pub fn get_courses_from_db(conn: &mut PgConnection, faculty: Option<i16>, search
     // the total count without the limit that's used for pagination
     let total_count = count_query.select(count_star()).get_result::<i64>(conn)?;
 
-    query = query.limit(limit);
+    // One more than the page size, to know whether there's a next page
+    query = query.limit(limit + 1);
+
+    // A cursor continues after the last course of the previous page, otherwise fall back to page numbers
+    match &page.cursor {
+        Some(cursor) => query = query.filter(after_course_cursor(cursor, relevance)),
+        None => {
+            let offset_page: i64 = sanitize_page_input(page.page);
+            query = query.offset((offset_page - 1) * limit);
+        }
+    }
 
-    let offset_page: i64 = sanitize_page_input(page);
-    query = query
-        .offset((offset_page - 1) * limit)
-        .order(courses::course_id.asc());
+    query = match page.sort {
+        CourseSort::CourseId => query.order(courses::course_id.asc()),
+        CourseSort::Name => query.order((courses::course_name.asc(), courses::course_id.asc())),
+        CourseSort::Resources => query.order((resource_count().desc(), courses::course_id.asc())),
+        CourseSort::Relevance => query.order((relevance().desc(), courses::course_id.asc()))
+    };
     
-    return Ok(GetCoursesResponse { courses: query.load(conn)?, total_courses: total_count });
-}
\ No newline at end of file
+    let mut courses = query.select((Course::as_select(), resource_count(), relevance())).load::<(Course, i64, f64)>(conn)?;
+
+    let mut next_cursor = None;
+    if courses.len() as i64 > limit {
+        courses.truncate(limit as usize);
+        next_cursor = courses.last().map(|(course, resource_count, relevance)| {
+            let key = match page.sort {
+                CourseSort::CourseId => CourseCursorKey::CourseId,
+                CourseSort::Name => CourseCursorKey::Name(course.course_name.clone()),
+                CourseSort::Resources => CourseCursorKey::Resources(*resource_count),
+                CourseSort::Relevance => CourseCursorKey::Relevance(*relevance)
+            };
+            CourseCursor { course_id: course.course_id.clone(), key }.encode()
+        });
+    }
+
+    let course_ids: Vec<String> = courses.iter().map(|(course, _, _)| course.course_id.clone()).collect();
+    let mut ratings = get_course_rating_summaries_from_db(conn, &course_ids)?;
+    let courses = courses.into_iter()
+        .map(|(course, _, _)| CourseMetadata { ratings: ratings.remove(&course.course_id).unwrap_or_default(), course })
+        .collect();
+
+    return Ok(GetCoursesResponse { courses, total_courses: total_count, next_cursor });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use futures_util::stream;
+
+    use super::*;
+    use crate::resource_uploads::{course_resource_file_key, ResourceFileUploads};
+    use crate::semesters::Semester;
+    use crate::test_support::{insert_test_course, test_connection, TempDir};
+
+    fn resource_payload(course_id: &str) -> InsertCourseResource {
+        InsertCourseResource {
+            title: "Midterm".to_string(),
+            subtitle: None,
+            course_id: course_id.to_string(),
+            resource_type: ResourceType::Exams,
+            semester: Semester::First,
+            academic_year: 2024,
+            issolved: false
+        }
+    }
+
+    async fn upload_files(dir: &TempDir, course_id: &str, resource_id: Uuid, file_names: &[&str]) -> Vec<CourseResourceFile> {
+        let mut uploads = ResourceFileUploads::new(Arc::new(dir.storage()), course_id.to_string(), resource_id, 2);
+        for file_name in file_names {
+            uploads.upload(file_name, stream::iter([Ok::<Bytes, std::io::Error>(Bytes::from_static(b"content"))])).await.unwrap();
+        }
+        uploads.finish().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn inserts_the_resource_and_its_files() {
+        let Some(mut conn) = test_connection() else { return };
+        let dir = TempDir::new();
+        let course_id = insert_test_course(&mut conn);
+        let resource_id = Uuid::new_v4();
+        let files = upload_files(&dir, &course_id, resource_id, &["a.pdf", "b.pdf"]).await;
+
+        insert_course_resource_into_db(&mut conn, &dir.storage(), resource_id, course_id.clone(), resource_payload(&course_id), files, None).await.unwrap();
+
+        let files = get_course_resource_with_files_from_db(&mut conn, resource_id).unwrap().files;
+        assert_eq!(files.len(), 2);
+        for file in files {
+            assert!(dir.contains(&course_resource_file_key(&course_id, resource_id, file.file_id, &file.file_name)));
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_insert_deletes_the_uploaded_files() {
+        let Some(mut conn) = test_connection() else { return };
+        let dir = TempDir::new();
+        let course_id = insert_test_course(&mut conn);
+        let resource_id = Uuid::new_v4();
+        let mut files = upload_files(&dir, &course_id, resource_id, &["a.pdf", "b.pdf"]).await;
+        // The resource row goes in before the file rows, which then fail on the duplicate id
+        let duplicate = CourseResourceFile { file_id: files[0].file_id, file_name: files[0].file_name.clone(), file_url: files[0].file_url.clone(), resource_id, is_hidden: false };
+        files.push(duplicate);
+
+        let inserted = insert_course_resource_into_db(&mut conn, &dir.storage(), resource_id, course_id.clone(), resource_payload(&course_id), files, None).await;
+        assert!(inserted.is_err());
+
+        assert_eq!(dir.files(), Vec::<String>::new());
+        let resources = course_resources::table.filter(course_resources::resource_id.eq(resource_id)).count().get_result::<i64>(&mut conn).unwrap();
+        let file_rows = schema::course_resource_files::table.filter(schema::course_resource_files::resource_id.eq(resource_id)).count().get_result::<i64>(&mut conn).unwrap();
+        assert_eq!((resources, file_rows), (0, 0));
+    }
+}
@@ -0,0 +1,103 @@
+//! Searching courses by code or name.
+//! Codes match with or without spaces ("CS 116" finds CS116), names match by word prefix ("comp fund" finds
+//! Computing Fundamentals) and by trigram similarity so small typos still find the course.
+//! The indexes behind this are in the `course_search_indexes` migration.
+use diesel::dsl::sql;
+use diesel::expression::BoxableExpression;
+use diesel::pg::Pg;
+use diesel::sql_types::{Bool, Double, Text};
+
+use crate::schema::courses;
+
+/// Uppercases a course code and drops everything that isn't a letter or digit, "cs 116" becomes "CS116"
+pub fn normalize_course_code(term: &str) -> String {
+    term.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_uppercase).collect()
+}
+
+/// Escapes the characters LIKE treats specially, so "100%" only matches a literal "100%".
+/// Relies on backslash being the default escape character in Postgres
+pub fn escape_like(term: &str) -> String {
+    let mut escaped = String::with_capacity(term.len());
+    for c in term.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Turns the words of a search term into a tsquery matching names containing words starting with each of them,
+/// "comp fund" becomes "comp:* & fund:*". `None` if there are no words
+pub fn prefix_tsquery(term: &str) -> Option<String> {
+    let words: Vec<String> = term
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("{}:*", word.to_lowercase()))
+        .collect();
+
+    if words.is_empty() {
+        return None;
+    }
+
+    Some(words.join(" & "))
+}
+
+pub struct CourseSearch {
+    term: String,
+    code: String,
+    tsquery: String,
+}
+
+impl CourseSearch {
+    /// `None` for terms without any letters or digits, which would match everything
+    pub fn new(term: &str) -> Option<Self> {
+        let term = term.trim();
+        let tsquery = prefix_tsquery(term)?;
+        Some(CourseSearch { term: term.to_string(), code: normalize_course_code(term), tsquery })
+    }
+
+    /// Whether a course matches the search at all
+    pub fn matches(&self) -> Box<dyn BoxableExpression<courses::table, Pg, SqlType = Bool>> {
+        Box::new(
+            sql::<Bool>("(courses.course_id ILIKE ").bind::<Text, _>(format!("%{}%", escape_like(&self.code)))
+                .sql(" OR courses.course_name ILIKE ").bind::<Text, _>(format!("%{}%", escape_like(&self.term)))
+                .sql(" OR to_tsvector('simple', courses.course_name) @@ to_tsquery('simple', ").bind::<Text, _>(self.tsquery.clone())
+                .sql(") OR ").bind::<Text, _>(self.term.clone()).sql(" <% courses.course_name)")
+        )
+    }
+
+    /// Higher is more relevant. An exact code beats a code prefix, which beats any name match,
+    /// and name matches are ranked by how well the words and trigrams match
+    pub fn relevance(&self) -> Box<dyn BoxableExpression<courses::table, Pg, SqlType = Double>> {
+        Box::new(
+            sql::<Double>("((CASE WHEN courses.course_id = ").bind::<Text, _>(self.code.clone())
+                .sql(" THEN 4 WHEN courses.course_id LIKE ").bind::<Text, _>(format!("{}%", escape_like(&self.code)))
+                .sql(" THEN 2 ELSE 0 END) + ts_rank(to_tsvector('simple', courses.course_name), to_tsquery('simple', ").bind::<Text, _>(self.tsquery.clone())
+                .sql(")) + word_similarity(").bind::<Text, _>(self.term.clone()).sql(", courses.course_name))::float8")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_like_wildcards() {
+        assert_eq!(escape_like("100%_done\\"), "100\\%\\_done\\\\");
+        assert_eq!(escape_like("Computing Fundamentals"), "Computing Fundamentals");
+    }
+
+    #[test]
+    fn normalizes_course_codes() {
+        assert_eq!(normalize_course_code(" cs 116 "), "CS116");
+        assert_eq!(normalize_course_code("%_"), "");
+    }
+
+    #[test]
+    fn builds_prefix_tsqueries() {
+        assert_eq!(prefix_tsquery("Comp  fund!").as_deref(), Some("comp:* & fund:*"));
+        assert_eq!(prefix_tsquery("%_ "), None);
+    }
+}
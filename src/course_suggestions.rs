@@ -0,0 +1,167 @@
+//! Typeahead for the course search box at `GET /v1/courses/suggest`.
+//! Suggestions come from an in-memory index of every course so they're cheap enough to request on every keystroke.
+//! The index is rebuilt from the database whenever courses are changed through the backend, and leaves out inactive courses.
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::RwLock;
+
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use diesel::prelude::*;
+
+use crate::course_search::normalize_course_code;
+use crate::models::{error_response, Course, CourseSuggestion, SuggestCoursesQuery};
+use crate::schema::courses;
+use crate::state::AppState;
+
+const DEFAULT_SUGGESTIONS: usize = 8;
+const MAX_SUGGESTIONS: usize = 20;
+
+fn name_words(name: &str) -> impl Iterator<Item = String> + '_ {
+    name.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(str::to_lowercase)
+}
+
+/// Courses sorted by id, and the words of their names pointing back at them
+#[derive(Default)]
+pub struct CourseSuggestionIndex {
+    courses: Vec<CourseSuggestion>,
+    name_words: BTreeMap<String, BTreeSet<usize>>,
+}
+
+impl CourseSuggestionIndex {
+    pub fn new(mut courses: Vec<CourseSuggestion>) -> Self {
+        courses.sort_by(|a, b| a.course_id.cmp(&b.course_id));
+
+        let mut index: BTreeMap<String, BTreeSet<usize>> = BTreeMap::new();
+        for (position, course) in courses.iter().enumerate() {
+            for word in name_words(&course.course_name) {
+                index.entry(word).or_default().insert(position);
+            }
+        }
+
+        CourseSuggestionIndex { courses, name_words: index }
+    }
+
+    // Every course with a name word starting with `prefix`
+    fn courses_with_word_prefix(&self, prefix: &str) -> BTreeSet<usize> {
+        self.name_words
+            .range(prefix.to_string()..)
+            .take_while(|(word, _)| word.starts_with(prefix))
+            .flat_map(|(_, positions)| positions.iter().copied())
+            .collect()
+    }
+
+    /// Courses whose id starts with the query come first ("cs 11" suggests CS115, CS116...),
+    /// then courses with a name word starting with each word of the query ("comp fund" suggests Computing Fundamentals)
+    pub fn suggest(&self, query: &str, limit: usize) -> Vec<CourseSuggestion> {
+        let mut suggested: Vec<usize> = Vec::new();
+
+        let code = normalize_course_code(query);
+        if !code.is_empty() {
+            let start = self.courses.partition_point(|course| course.course_id.as_str() < code.as_str());
+            suggested.extend((start..self.courses.len()).take_while(|position| self.courses[*position].course_id.starts_with(&code)).take(limit));
+        }
+
+        let mut matches: Option<BTreeSet<usize>> = None;
+        for word in name_words(query) {
+            let with_word = self.courses_with_word_prefix(&word);
+            matches = Some(match matches {
+                Some(matches) => matches.intersection(&with_word).copied().collect(),
+                None => with_word
+            });
+        }
+
+        let mut name_matches: Vec<usize> = matches.unwrap_or_default().into_iter().filter(|position| !suggested.contains(position)).collect();
+        name_matches.sort_by(|a, b| self.courses[*a].course_name.cmp(&self.courses[*b].course_name));
+        suggested.extend(name_matches);
+
+        suggested.into_iter().take(limit).map(|position| self.courses[position].clone()).collect()
+    }
+}
+
+/// The index shared by every request
+#[derive(Default)]
+pub struct CourseSuggestions {
+    index: RwLock<CourseSuggestionIndex>,
+}
+
+impl CourseSuggestions {
+    /// Rebuilds the index from the courses table, call this after changing courses
+    pub fn refresh(&self, conn: &mut PgConnection) -> Result<(), diesel::result::Error> {
+        let courses = courses::table
+            .filter(courses::is_active.eq(true))
+            .select(Course::as_select())
+            .load(conn)?
+            .into_iter()
+            .map(|course| CourseSuggestion { course_id: course.course_id, course_name: course.course_name })
+            .collect();
+
+        let index = CourseSuggestionIndex::new(courses);
+        *self.index.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = index;
+        Ok(())
+    }
+
+    pub fn suggest(&self, query: &str, limit: usize) -> Vec<CourseSuggestion> {
+        self.index.read().unwrap_or_else(|poisoned| poisoned.into_inner()).suggest(query, limit)
+    }
+}
+
+pub async fn suggest_courses(State(state): State<AppState>, Query(query): Query<SuggestCoursesQuery>) -> Result<impl IntoResponse, StatusCode> {
+    let limit = query.limit.unwrap_or(DEFAULT_SUGGESTIONS);
+    if limit == 0 || limit > MAX_SUGGESTIONS {
+        return Ok(error_response(StatusCode::BAD_REQUEST, format!("limit must be between 1 and {}", MAX_SUGGESTIONS)));
+    }
+
+    Ok(Json(state.course_suggestions.suggest(&query.q, limit)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> CourseSuggestionIndex {
+        let courses = [
+            ("CS116", "Computing Fundamentals"),
+            ("CS115", "Introduction to Programming"),
+            ("CS201", "Data Structures"),
+            ("CE110", "Computer Engineering Basics"),
+            ("DATA200", "Data Mining"),
+            ("MATH101", "Calculus I"),
+        ];
+        CourseSuggestionIndex::new(courses.iter().map(|(id, name)| CourseSuggestion { course_id: id.to_string(), course_name: name.to_string() }).collect())
+    }
+
+    fn suggested_ids(query: &str, limit: usize) -> Vec<String> {
+        index().suggest(query, limit).into_iter().map(|course| course.course_id).collect()
+    }
+
+    #[test]
+    fn code_prefix_matches_come_first() {
+        assert_eq!(suggested_ids("cs 11", 10), ["CS115", "CS116"]);
+        assert_eq!(suggested_ids("data", 10), ["DATA200", "CS201"]);
+    }
+
+    #[test]
+    fn courses_matching_both_ways_are_suggested_once() {
+        assert_eq!(suggested_ids("data", 10).iter().filter(|id| *id == "DATA200").count(), 1);
+    }
+
+    #[test]
+    fn every_word_has_to_match_a_name_word() {
+        assert_eq!(suggested_ids("comp", 10), ["CE110", "CS116"]);
+        assert_eq!(suggested_ids("comp fund", 10), ["CS116"]);
+        assert_eq!(suggested_ids("Fund, comp!", 10), ["CS116"]);
+        assert!(suggested_ids("comp mining", 10).is_empty());
+    }
+
+    #[test]
+    fn caps_suggestions_at_limit() {
+        assert_eq!(suggested_ids("cs", 2), ["CS115", "CS116"]);
+        assert_eq!(suggested_ids("data", 1), ["DATA200"]);
+    }
+
+    #[test]
+    fn empty_queries_suggest_nothing() {
+        for query in ["", "   ", "-- !"] {
+            assert!(suggested_ids(query, 10).is_empty(), "{:?}", query);
+        }
+    }
+}
@@ -1,6 +1,24 @@
-/* Not sure if this'll ever be used, but this maps out the faculties and their int values */
+//! The faculties courses belong to, stored and sent as their int values.
+//! Their display names live in the `faculties` table and are served at `GET /v1/faculties`
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::dsl::count;
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::SmallInt;
+use serde::{Deserialize, Serialize};
 
-enum Faculties { 
+use crate::connection::DbConn;
+use crate::models::{error_response, Faculty, FacultyResponse};
+use crate::schema::{courses, faculties};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = SmallInt)]
+#[serde(try_from = "i16", into = "i16")]
+#[repr(i16)]
+pub enum Faculties {
     BusinessSchool = 0,
     GraduateSchoolOfBusiness,
     SchoolOfAppliedHumanities,
@@ -11,4 +29,65 @@ enum Faculties {
     SchoolOfLanguages,
     SchoolOfNaturalResourcesEngineeringAndManagement,
     SchoolOfNursing
-}
\ No newline at end of file
+}
+
+impl Faculties {
+    pub const ALL: [Faculties; 10] = [
+        Faculties::BusinessSchool,
+        Faculties::GraduateSchoolOfBusiness,
+        Faculties::SchoolOfAppliedHumanities,
+        Faculties::SchoolOfAppliedMedicalSciences,
+        Faculties::SchoolOfAppliedTechnicalSciences,
+        Faculties::SchoolOfArchitectureAndBuiltEnvironment,
+        Faculties::SchoolOfElectricalEngineeringAndInformationTechnology,
+        Faculties::SchoolOfLanguages,
+        Faculties::SchoolOfNaturalResourcesEngineeringAndManagement,
+        Faculties::SchoolOfNursing
+    ];
+}
+
+impl From<Faculties> for i16 {
+    fn from(faculty: Faculties) -> Self {
+        faculty as i16
+    }
+}
+
+impl TryFrom<i16> for Faculties {
+    type Error = String;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        Faculties::ALL.into_iter()
+            .find(|faculty| i16::from(*faculty) == value)
+            .ok_or_else(|| format!("Invalid faculty {} (Must be between 0 and {})", value, Faculties::ALL.len() - 1))
+    }
+}
+
+impl ToSql<SmallInt, Pg> for Faculties {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <i16 as ToSql<SmallInt, Pg>>::to_sql(&i16::from(*self), &mut out.reborrow())
+    }
+}
+
+impl FromSql<SmallInt, Pg> for Faculties {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        Ok(Faculties::try_from(<i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)?)?)
+    }
+}
+
+/// Every faculty with its display names and how many active courses it has
+pub async fn get_faculties(DbConn(mut conn): DbConn) -> Result<impl IntoResponse, StatusCode> {
+    let found = faculties::table
+        .left_join(courses::table.on(courses::course_faculty.eq(faculties::faculty_id).and(courses::is_active.eq(true))))
+        .group_by(faculties::faculty_id)
+        .order(faculties::faculty_id.asc())
+        .select((Faculty::as_select(), count(courses::course_id.nullable())))
+        .load::<(Faculty, i64)>(&mut conn);
+
+    match found {
+        Ok(found) => {
+            let found: Vec<FacultyResponse> = found.into_iter().map(|(faculty, course_count)| FacultyResponse { faculty, course_count }).collect();
+            Ok(Json(found).into_response())
+        },
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
@@ -0,0 +1,479 @@
+//! Background job re-checking course links, since Drive folders and Telegram invites go dead all the time.
+//! Every run checks the visible links that weren't checked in the last day, and a link failing
+//! `LINK_FAILURE_THRESHOLD` checks in a row is hidden. Requests go through `HttpClient` so tests can point it at a stub.
+//! Links are validated again before every request, and the client never connects to private addresses,
+//! so a stored link (or a domain that later starts resolving to the internal network) can't be used to reach it.
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::{stream, StreamExt};
+use diesel::prelude::*;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use uuid::Uuid;
+
+use crate::connection::{get_connection, DbPool};
+use crate::link_validation::{is_private_ip, validate_link_url};
+use crate::schema::course_resource_links;
+
+/// Links checked per run
+const LINKS_PER_RUN: i64 = 100;
+const CONCURRENT_CHECKS: usize = 8;
+const RECHECK_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_REDIRECTS: usize = 10;
+
+#[async_trait]
+pub trait HttpClient: Send + Sync {
+    /// Status code of requesting the URL, `Err` if no response came back at all
+    async fn status(&self, url: &str) -> Result<u16, String>;
+}
+
+/// Resolves hostnames with the system resolver, leaving out private addresses
+struct PublicAddressResolver;
+
+impl Resolve for PublicAddressResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?
+                .filter(|addr| !is_private_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} doesn't resolve to any public address", host).into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+pub struct ReqwestHttpClient {
+    client: reqwest::Client,
+}
+
+impl ReqwestHttpClient {
+    pub fn new() -> Self {
+        let client = Self::client_builder().build().expect("Failed to build HTTP client");
+        ReqwestHttpClient { client }
+    }
+
+    fn client_builder() -> reqwest::ClientBuilder {
+        // Redirects go through the same checks as the links themselves, and refusing one fails the request
+        // rather than returning the redirect, which would count as healthy
+        let redirects = reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("Too many redirects")
+            } else if let Err(e) = validate_link_url(attempt.url().as_str()) {
+                let message = format!("Refused to follow redirect to {}: {}", attempt.url(), e);
+                attempt.error(message)
+            } else {
+                attempt.follow()
+            }
+        });
+        reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(redirects)
+            .dns_resolver(Arc::new(PublicAddressResolver))
+            .user_agent("GJUFilesLinkChecker/1.0")
+    }
+}
+
+#[async_trait]
+impl HttpClient for ReqwestHttpClient {
+    // HEAD first since it skips the body, but plenty of sites refuse it so anything but a success is retried with GET
+    async fn status(&self, url: &str) -> Result<u16, String> {
+        if let Ok(response) = self.client.head(url).send().await {
+            if response.status().is_success() {
+                return Ok(response.status().as_u16());
+            }
+        }
+
+        match self.client.get(url).send().await {
+            Ok(response) => Ok(response.status().as_u16()),
+            Err(e) => Err(e.to_string())
+        }
+    }
+}
+
+pub struct LinkHealthConfig {
+    /// How often to look for links to check, zero turns the checker off
+    pub interval: Duration,
+    /// Failed checks in a row before a link is hidden
+    pub failure_threshold: i32,
+}
+
+/// Configured with `LINK_CHECK_INTERVAL_SECS` (default 3600, 0 disables it) and `LINK_FAILURE_THRESHOLD` (default 3)
+pub fn link_health_config_from_env() -> LinkHealthConfig {
+    LinkHealthConfig {
+        interval: Duration::from_secs(dotenvy::var("LINK_CHECK_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(3600)),
+        failure_threshold: dotenvy::var("LINK_FAILURE_THRESHOLD").ok().and_then(|v| v.parse().ok()).filter(|threshold| *threshold > 0).unwrap_or(3)
+    }
+}
+
+/// Redirects and successes are fine, error statuses and requests that got no response at all are failures
+fn is_healthy(status: Option<u16>) -> bool {
+    status.is_some_and(|status| status < 400)
+}
+
+/// Saves the result of checking a link, hiding it once it failed `failure_threshold` times in a row.
+/// Returns whether the link is hidden now
+pub fn record_link_check(conn: &mut PgConnection, link_id: Uuid, status: Option<u16>, failure_threshold: i32) -> Result<bool, diesel::result::Error> {
+    let last_status = status.map(|status| status as i16);
+    let checked = diesel::update(course_resource_links::table.find(link_id));
+
+    if is_healthy(status) {
+        return checked
+            .set((course_resource_links::last_checked.eq(chrono::Utc::now()), course_resource_links::last_status.eq(last_status), course_resource_links::consecutive_failures.eq(0)))
+            .returning(course_resource_links::is_hidden)
+            .get_result(conn);
+    }
+
+    checked
+        .set((
+            course_resource_links::last_checked.eq(chrono::Utc::now()),
+            course_resource_links::last_status.eq(last_status),
+            course_resource_links::consecutive_failures.eq(course_resource_links::consecutive_failures + 1),
+            course_resource_links::is_hidden.eq(course_resource_links::is_hidden.or((course_resource_links::consecutive_failures + 1).ge(failure_threshold)))
+        ))
+        .returning(course_resource_links::is_hidden)
+        .get_result(conn)
+}
+
+fn load_due_links(conn: &mut PgConnection) -> Result<Vec<(Uuid, String)>, diesel::result::Error> {
+    let due_before = chrono::Utc::now() - RECHECK_AFTER;
+    course_resource_links::table
+        .filter(course_resource_links::is_hidden.eq(false))
+        .filter(course_resource_links::last_checked.is_null().or(course_resource_links::last_checked.lt(due_before)))
+        .order(course_resource_links::last_checked.asc().nulls_first())
+        .limit(LINKS_PER_RUN)
+        .select((course_resource_links::link_id, course_resource_links::link_url))
+        .load::<(Uuid, String)>(conn)
+}
+
+/// Checks the links that are due, never checked ones first.
+/// Links that don't pass `validate_link_url` anymore are never requested and count as failed checks.
+/// Connections are only checked out to load and record the links, not while waiting on the requests
+pub async fn check_links(pool: &DbPool, client: &dyn HttpClient, config: &LinkHealthConfig) -> Result<(), String> {
+    let links = {
+        let mut conn = get_connection(pool).await.map_err(|e| e.0)?;
+        load_due_links(&mut conn).map_err(|e| e.to_string())?
+    };
+
+    let results: Vec<(Uuid, String, Option<u16>)> = stream::iter(links)
+        .map(|(link_id, link_url)| async move {
+            let status = match validate_link_url(&link_url) {
+                Ok(_) => client.status(&link_url).await.ok(),
+                Err(e) => {
+                    eprintln!("Not checking link {} ({}): {}", link_id, link_url, e);
+                    None
+                }
+            };
+            (link_id, link_url, status)
+        })
+        .buffer_unordered(CONCURRENT_CHECKS)
+        .collect()
+        .await;
+
+    for (link_id, link_url, status) in results {
+        let mut conn = get_connection(pool).await.map_err(|e| e.0)?;
+        match record_link_check(&mut conn, link_id, status, config.failure_threshold) {
+            Ok(true) if !is_healthy(status) => println!("Hid link {} ({}) after it failed {} checks in a row", link_id, link_url, config.failure_threshold),
+            Ok(_) => {},
+            Err(e) => eprintln!("Failed to record check of link {}: {}", link_id, e)
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs `check_links` every `config.interval` in the background, unless it's turned off
+pub fn spawn_link_health_checker(pool: DbPool, client: Arc<dyn HttpClient>, config: LinkHealthConfig) {
+    if config.interval.is_zero() {
+        println!("Link health checks are turned off (LINK_CHECK_INTERVAL_SECS is 0)");
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = check_links(&pool, client.as_ref(), &config).await {
+                eprintln!("Link health check failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::link_kinds::LinkKind;
+    use crate::test_support::{insert_test_course, test_connection, test_pool};
+
+    /// Public looking host the real client is pointed at the stub server with
+    const STUB_HOST: &str = "links.example.com";
+
+    /// Answers with a fixed status per URL, and no response at all for the rest
+    struct StubHttpClient {
+        statuses: HashMap<String, u16>,
+        requested: Mutex<Vec<String>>,
+    }
+
+    impl StubHttpClient {
+        fn new(statuses: &[(&str, u16)]) -> Self {
+            StubHttpClient { statuses: statuses.iter().map(|(url, status)| (url.to_string(), *status)).collect(), requested: Mutex::new(Vec::new()) }
+        }
+    }
+
+    #[async_trait]
+    impl HttpClient for StubHttpClient {
+        async fn status(&self, url: &str) -> Result<u16, String> {
+            self.requested.lock().unwrap().push(url.to_string());
+            self.statuses.get(url).copied().ok_or_else(|| "Connection refused".to_string())
+        }
+    }
+
+    /// HTTP server on a local port, answering by path and remembering the "METHOD /path" of every request
+    struct StubServer {
+        port: u16,
+        requests: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl StubServer {
+        async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let requests = Arc::new(Mutex::new(Vec::new()));
+
+            let seen = requests.clone();
+            tokio::spawn(async move {
+                while let Ok((mut socket, _)) = listener.accept().await {
+                    let seen = seen.clone();
+                    tokio::spawn(async move {
+                        let mut head = Vec::new();
+                        let mut buffer = [0u8; 1024];
+                        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+                            match socket.read(&mut buffer).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(read) => head.extend_from_slice(&buffer[..read])
+                            }
+                        }
+
+                        let head = String::from_utf8_lossy(&head).to_string();
+                        let mut request_line = head.split_whitespace();
+                        let (method, path) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+                        seen.lock().unwrap().push(format!("{} {}", method, path));
+
+                        let (status, location) = match (method, path) {
+                            (_, "/ok") => (200, None),
+                            ("HEAD", "/no-head") => (405, None),
+                            (_, "/no-head") => (200, None),
+                            (_, "/redirect-ok") => (302, Some(format!("http://{}:{}/ok", STUB_HOST, port))),
+                            (_, "/redirect-private") => (302, Some(format!("http://localhost:{}/ok", port))),
+                            _ => (404, None)
+                        };
+                        let location = location.map(|location| format!("Location: {}\r\n", location)).unwrap_or_default();
+                        let response = format!("HTTP/1.1 {} Stub\r\nContent-Length: 0\r\nConnection: close\r\n{}\r\n", status, location);
+                        _ = socket.write_all(response.as_bytes()).await;
+                    });
+                }
+            });
+
+            StubServer { port, requests }
+        }
+
+        fn url(&self, path: &str) -> String {
+            format!("http://{}:{}{}", STUB_HOST, self.port, path)
+        }
+
+        /// The real client, with `STUB_HOST` resolving to the server
+        fn client(&self) -> ReqwestHttpClient {
+            let client = ReqwestHttpClient::client_builder()
+                .resolve(STUB_HOST, SocketAddr::from(([127, 0, 0, 1], self.port)))
+                .build()
+                .unwrap();
+            ReqwestHttpClient { client }
+        }
+
+        fn take_requests(&self) -> Vec<String> {
+            std::mem::take(&mut *self.requests.lock().unwrap())
+        }
+    }
+
+    fn insert_test_link(conn: &mut PgConnection, course_id: &str, url: &str) -> Uuid {
+        let link_id = Uuid::new_v4();
+        diesel::insert_into(course_resource_links::table)
+            .values((
+                course_resource_links::link_id.eq(link_id),
+                course_resource_links::link_title.eq("Test link"),
+                course_resource_links::link_url.eq(url),
+                course_resource_links::course_id.eq(course_id),
+                course_resource_links::link_kind.eq(LinkKind::from_url(url))
+            ))
+            .execute(conn)
+            .unwrap();
+        link_id
+    }
+
+    fn link_health(conn: &mut PgConnection, link_id: Uuid) -> (i32, bool) {
+        course_resource_links::table
+            .find(link_id)
+            .select((course_resource_links::consecutive_failures, course_resource_links::is_hidden))
+            .first(conn)
+            .unwrap()
+    }
+
+    // So the checker only sees the links added by the test. The table lock keeps tests doing this from deadlocking each other
+    fn mark_existing_links_checked(conn: &mut PgConnection) {
+        diesel::sql_query("LOCK TABLE course_resource_links IN SHARE ROW EXCLUSIVE MODE").execute(conn).unwrap();
+        diesel::update(course_resource_links::table)
+            .set(course_resource_links::last_checked.eq(chrono::Utc::now()))
+            .execute(conn)
+            .unwrap();
+    }
+
+    fn mark_due(conn: &mut PgConnection, link_ids: &[Uuid]) {
+        diesel::update(course_resource_links::table.filter(course_resource_links::link_id.eq_any(link_ids)))
+            .set(course_resource_links::last_checked.eq(None::<chrono::DateTime<chrono::Utc>>))
+            .execute(conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn hides_link_once_failure_threshold_is_reached() {
+        let Some(mut conn) = test_connection() else { return };
+        let course_id = insert_test_course(&mut conn);
+        let link_id = insert_test_link(&mut conn, &course_id, "https://example.com/notes");
+
+        assert!(!record_link_check(&mut conn, link_id, Some(404), 3).unwrap());
+        assert!(!record_link_check(&mut conn, link_id, None, 3).unwrap());
+        assert_eq!(link_health(&mut conn, link_id), (2, false));
+
+        assert!(record_link_check(&mut conn, link_id, Some(500), 3).unwrap());
+        assert_eq!(link_health(&mut conn, link_id), (3, true));
+    }
+
+    #[test]
+    fn success_resets_consecutive_failures() {
+        let Some(mut conn) = test_connection() else { return };
+        let course_id = insert_test_course(&mut conn);
+        let link_id = insert_test_link(&mut conn, &course_id, "https://example.com/notes");
+
+        record_link_check(&mut conn, link_id, Some(404), 3).unwrap();
+        record_link_check(&mut conn, link_id, Some(404), 3).unwrap();
+        assert!(!record_link_check(&mut conn, link_id, Some(301), 3).unwrap());
+        assert_eq!(link_health(&mut conn, link_id), (0, false));
+
+        assert!(!record_link_check(&mut conn, link_id, Some(404), 3).unwrap());
+        assert_eq!(link_health(&mut conn, link_id), (1, false));
+    }
+
+    #[tokio::test]
+    async fn check_links_records_results_and_skips_invalid_links() {
+        let Some(pool) = test_pool() else { return };
+        let (healthy, dead, private) = {
+            let mut conn = pool.get().unwrap();
+            mark_existing_links_checked(&mut conn);
+            let course_id = insert_test_course(&mut conn);
+            // The last one was saved before links were validated
+            (
+                insert_test_link(&mut conn, &course_id, "https://example.com/healthy"),
+                insert_test_link(&mut conn, &course_id, "https://example.com/dead"),
+                insert_test_link(&mut conn, &course_id, "http://127.0.0.1/admin")
+            )
+        };
+
+        let client = StubHttpClient::new(&[("https://example.com/healthy", 200), ("https://example.com/dead", 404)]);
+        let config = LinkHealthConfig { interval: Duration::ZERO, failure_threshold: 2 };
+
+        check_links(&pool, &client, &config).await.unwrap();
+        {
+            let mut conn = pool.get().unwrap();
+            assert_eq!(link_health(&mut conn, healthy), (0, false));
+            assert_eq!(link_health(&mut conn, dead), (1, false));
+            assert_eq!(link_health(&mut conn, private), (1, false));
+        }
+
+        // Everything was just checked, so nothing is due
+        check_links(&pool, &client, &config).await.unwrap();
+        assert_eq!(link_health(&mut pool.get().unwrap(), dead), (1, false));
+
+        mark_due(&mut pool.get().unwrap(), &[healthy, dead, private]);
+        check_links(&pool, &client, &config).await.unwrap();
+        {
+            let mut conn = pool.get().unwrap();
+            assert_eq!(link_health(&mut conn, healthy), (0, false));
+            assert_eq!(link_health(&mut conn, dead), (2, true));
+            assert_eq!(link_health(&mut conn, private), (2, true));
+        }
+
+        let requested = client.requested.lock().unwrap();
+        assert_eq!(requested.len(), 4);
+        assert!(!requested.iter().any(|url| url.contains("127.0.0.1")));
+    }
+
+    #[tokio::test]
+    async fn real_client_retries_with_get_when_head_fails() {
+        let server = StubServer::start().await;
+        let client = server.client();
+
+        assert_eq!(client.status(&server.url("/ok")).await, Ok(200));
+        assert_eq!(server.take_requests(), ["HEAD /ok"]);
+
+        assert_eq!(client.status(&server.url("/no-head")).await, Ok(200));
+        assert_eq!(server.take_requests(), ["HEAD /no-head", "GET /no-head"]);
+
+        assert_eq!(client.status(&server.url("/dead")).await, Ok(404));
+        assert_eq!(server.take_requests(), ["HEAD /dead", "GET /dead"]);
+    }
+
+    #[tokio::test]
+    async fn real_client_refuses_redirects_to_private_addresses() {
+        let server = StubServer::start().await;
+        let client = server.client();
+
+        assert_eq!(client.status(&server.url("/redirect-ok")).await, Ok(200));
+        assert_eq!(server.take_requests(), ["HEAD /redirect-ok", "HEAD /ok"]);
+
+        // Refusing the redirect fails the check instead of returning the 302, which would count as healthy
+        assert!(client.status(&server.url("/redirect-private")).await.is_err());
+        assert_eq!(server.take_requests(), ["HEAD /redirect-private", "GET /redirect-private"]);
+    }
+
+    #[tokio::test]
+    async fn real_client_never_connects_to_private_addresses() {
+        let server = StubServer::start().await;
+        let client = server.client();
+
+        assert!(client.status(&format!("http://localhost:{}/ok", server.port)).await.is_err());
+        assert!(server.take_requests().is_empty());
+        assert!(PublicAddressResolver.resolve("localhost".parse().unwrap()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn check_links_counts_refused_redirects_as_failures() {
+        let Some(pool) = test_pool() else { return };
+        let server = StubServer::start().await;
+        let (healthy, redirected) = {
+            let mut conn = pool.get().unwrap();
+            mark_existing_links_checked(&mut conn);
+            let course_id = insert_test_course(&mut conn);
+            (insert_test_link(&mut conn, &course_id, &server.url("/redirect-ok")), insert_test_link(&mut conn, &course_id, &server.url("/redirect-private")))
+        };
+
+        let config = LinkHealthConfig { interval: Duration::ZERO, failure_threshold: 1 };
+        check_links(&pool, &server.client(), &config).await.unwrap();
+
+        let mut conn = pool.get().unwrap();
+        assert_eq!(link_health(&mut conn, healthy), (0, false));
+        assert_eq!(link_health(&mut conn, redirected), (1, true));
+    }
+}
@@ -0,0 +1,149 @@
+//! What a course link points to, detected from the host of its URL so clients can show an icon for it.
+//! Stored and sent as its snake_case name. To recognize another site, add a variant, match its hosts in
+//! `LinkKind::from_url` and widen the check constraint on `course_resource_links.link_kind` in a migration.
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = Text)]
+#[serde(try_from = "String", into = "&'static str")]
+pub enum LinkKind {
+    YouTube,
+    GoogleDrive,
+    Telegram,
+    Discord,
+    GitHub,
+    Other
+}
+
+/// Whether the host is the domain or one of its subdomains, so "m.youtube.com" is YouTube but "notyoutube.com" isn't
+pub fn is_host_of(host: &str, domains: &[&str]) -> bool {
+    domains.iter().any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+}
+
+impl LinkKind {
+    pub const ALL: [LinkKind; 6] = [LinkKind::YouTube, LinkKind::GoogleDrive, LinkKind::Telegram, LinkKind::Discord, LinkKind::GitHub, LinkKind::Other];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkKind::YouTube => "youtube",
+            LinkKind::GoogleDrive => "google_drive",
+            LinkKind::Telegram => "telegram",
+            LinkKind::Discord => "discord",
+            LinkKind::GitHub => "github",
+            LinkKind::Other => "other"
+        }
+    }
+
+    /// Other for URLs that can't be parsed or don't belong to any of the known sites
+    pub fn from_url(url: &str) -> LinkKind {
+        let Some(host) = Url::parse(url.trim()).ok().and_then(|url| url.host_str().map(str::to_lowercase)) else {
+            return LinkKind::Other;
+        };
+
+        if is_host_of(&host, &["youtube.com", "youtu.be"]) {
+            return LinkKind::YouTube;
+        }
+        if host == "drive.google.com" || host == "docs.google.com" {
+            return LinkKind::GoogleDrive;
+        }
+        if is_host_of(&host, &["t.me", "telegram.me", "telegram.org", "telegram.dog"]) {
+            return LinkKind::Telegram;
+        }
+        if is_host_of(&host, &["discord.gg", "discord.com", "discordapp.com"]) {
+            return LinkKind::Discord;
+        }
+        if is_host_of(&host, &["github.com", "github.io"]) {
+            return LinkKind::GitHub;
+        }
+
+        LinkKind::Other
+    }
+}
+
+impl From<LinkKind> for &'static str {
+    fn from(kind: LinkKind) -> Self {
+        kind.as_str()
+    }
+}
+
+impl TryFrom<String> for LinkKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        LinkKind::ALL.into_iter()
+            .find(|kind| kind.as_str() == value.trim())
+            .ok_or_else(|| {
+                let valid_kinds: Vec<&str> = LinkKind::ALL.iter().map(LinkKind::as_str).collect();
+                format!("Invalid link kind {} (Must be one of {})", value, valid_kinds.join(", "))
+            })
+    }
+}
+
+impl ToSql<Text, Pg> for LinkKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), &mut out.reborrow())
+    }
+}
+
+impl FromSql<Text, Pg> for LinkKind {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        Ok(LinkKind::try_from(<String as FromSql<Text, Pg>>::from_sql(bytes)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_known_sites() {
+        let cases = [
+            ("https://www.youtube.com/watch?v=abc", LinkKind::YouTube),
+            ("https://m.youtube.com/watch?v=abc", LinkKind::YouTube),
+            ("https://youtu.be/abc", LinkKind::YouTube),
+            ("https://drive.google.com/drive/folders/abc", LinkKind::GoogleDrive),
+            ("https://docs.google.com/document/d/abc", LinkKind::GoogleDrive),
+            ("https://t.me/joinchat/abc", LinkKind::Telegram),
+            ("https://discord.gg/abc", LinkKind::Discord),
+            ("https://github.com/someone/notes", LinkKind::GitHub),
+            ("https://user.github.io/notes", LinkKind::GitHub),
+        ];
+        for (url, kind) in cases {
+            assert_eq!(LinkKind::from_url(url), kind, "{}", url);
+        }
+    }
+
+    #[test]
+    fn ignores_lookalike_domains() {
+        for url in ["https://notyoutube.com/watch", "https://youtube.com.evil.io/watch", "https://mail.google.com/mail", "https://google.com/drive", "https://evilgithub.com/x"] {
+            assert_eq!(LinkKind::from_url(url), LinkKind::Other, "{}", url);
+        }
+    }
+
+    #[test]
+    fn ignores_host_case() {
+        assert_eq!(LinkKind::from_url("HTTPS://WWW.YOUTUBE.COM/watch?v=abc"), LinkKind::YouTube);
+        assert_eq!(LinkKind::from_url("https://Drive.Google.com/file/d/abc"), LinkKind::GoogleDrive);
+    }
+
+    #[test]
+    fn unparsable_urls_are_other() {
+        for url in ["", "youtube.com/watch?v=abc", "not a url", "mailto:someone@youtube.com"] {
+            assert_eq!(LinkKind::from_url(url), LinkKind::Other, "{}", url);
+        }
+    }
+
+    #[test]
+    fn round_trips_through_its_name() {
+        for kind in LinkKind::ALL {
+            assert_eq!(LinkKind::try_from(kind.as_str().to_string()), Ok(kind));
+        }
+        assert!(LinkKind::try_from("vimeo".to_string()).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Sending emails, currently only the verification codes for signing in.
+//! The transport is behind `Mailer` so development and tests don't need an SMTP server.
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+#[derive(Debug, thiserror::Error)]
+pub enum MailError {
+    #[error("Invalid email address: {0}")]
+    InvalidAddress(String),
+    #[error("Failed to send email: {0}")]
+    Transport(String),
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), MailError>;
+}
+
+/// Picks the mail transport with `MAIL_TRANSPORT`, either `smtp` or `log` (the default, which only prints emails)
+pub fn mailer_from_env() -> Arc<dyn Mailer> {
+    match dotenvy::var("MAIL_TRANSPORT").unwrap_or_default().to_lowercase().as_str() {
+        "smtp" => {
+            let host = dotenvy::var("SMTP_HOST").expect("SMTP_HOST must be set when MAIL_TRANSPORT is smtp");
+            let from = dotenvy::var("MAIL_FROM").unwrap_or("GJU Files <noreply@gjufiles.com>".to_string());
+            let credentials = match (dotenvy::var("SMTP_USERNAME"), dotenvy::var("SMTP_PASSWORD")) {
+                (Ok(username), Ok(password)) => Some(Credentials::new(username, password)),
+                _ => None
+            };
+
+            Arc::new(SmtpMailer::new(&host, credentials, &from).expect("Invalid SMTP configuration"))
+        },
+        _ => {
+            println!("Emails will be printed instead of sent (set MAIL_TRANSPORT=smtp to send them)");
+            Arc::new(LogMailer)
+        }
+    }
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(host: &str, credentials: Option<Credentials>, from: &str) -> Result<Self, MailError> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+            .map_err(|e| MailError::Transport(e.to_string()))?;
+        if let Some(credentials) = credentials {
+            builder = builder.credentials(credentials);
+        }
+
+        let from = from.parse().map_err(|_| MailError::InvalidAddress(from.to_string()))?;
+        Ok(SmtpMailer { transport: builder.build(), from })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), MailError> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse().map_err(|_| MailError::InvalidAddress(to.to_string()))?)
+            .subject(subject)
+            .body(body.to_string())
+            .map_err(|e| MailError::Transport(e.to_string()))?;
+
+        self.transport.send(message).await.map_err(|e| MailError::Transport(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Prints emails to stdout, for local development
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), MailError> {
+        println!("Email to {}: {}\n{}", to, subject, body);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub struct SentEmail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Keeps emails in memory instead of sending them, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryMailer {
+    pub sent: std::sync::Mutex<Vec<SentEmail>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), MailError> {
+        self.sent.lock().unwrap().push(SentEmail { to: to.to_string(), subject: subject.to_string(), body: body.to_string() });
+        Ok(())
+    }
+}
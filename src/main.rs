@@ -1,24 +1,56 @@
-use axum::{extract::{DefaultBodyLimit, Multipart, Query}, http::{header::{AUTHORIZATION, CONTENT_TYPE}, StatusCode}, response::IntoResponse, routing::{get, post}, Json, Router};
-use connection::establish_connection;
-use course_retreival::{get_course_details_from_db, get_courses_from_db, insert_course_link_into_db, insert_course_resource_into_db, CourseResourceUploadFile};
-use models::{GetCourseDetailsQuery, GetCoursesQuery, InsertCourseResource};
-
-use regex::Regex;
+// Early returns are used throughout the handlers for readability
+#![allow(clippy::needless_return)]
+// Handler helpers return the error `Response` directly so handlers can bail out with it
+#![allow(clippy::result_large_err)]
+
+use accounts::AuthenticatedUser;
+use axum::{extract::{DefaultBodyLimit, Multipart, Query, State}, http::{header::{AUTHORIZATION, CONTENT_TYPE}, StatusCode}, middleware, response::{IntoResponse, Response}, routing::{delete, get, patch, post}, Json, Router};
+use connection::{establish_pool, get_connection, DbConn};
+use course_retreival::{delete_course_resource_file_from_db, delete_course_resource_from_db, get_course_details_from_db, get_course_resource_from_db, get_course_resource_with_files_from_db, get_courses_from_db, courses_per_page, insert_course_link_into_db, insert_course_resource_files_into_db, insert_course_resource_into_db, update_course_resource_in_db, DeleteCourseResourceFileError, MAX_COURSES_PER_PAGE};
+use course_search::CourseSearch;
+use diesel::PgConnection;
+use models::{error_response, CourseCursor, CourseResource, CourseResourceChangeset, CourseResourceFilters, CourseSort, CoursesPage, EditCourseResource, GetCourseDetailsQuery, GetCoursesQuery, InsertCourseResource, User};
+use resource_uploads::{delete_resource_files_from_storage, ResourceFileUploads};
+use state::AppState;
+use storage::{file_content_type, StorageError};
 
 mod models;
 mod schema;
 mod faculties;
+mod resource_types;
+mod semesters;
 mod connection;
 mod course_retreival;
+mod course_links;
+mod link_kinds;
+mod link_validation;
+mod link_health;
+mod course_search;
+mod course_suggestions;
+mod search;
 mod course_initialization;
 mod authentication;
+mod storage;
+mod resource_uploads;
+mod mail;
+mod accounts;
+mod admin;
+mod reports;
+mod ratings;
+mod votes;
+mod comments;
+mod state;
+#[cfg(test)]
+mod test_support;
 
 use crate::models::ErrorResponse;
 use tower_http::cors::{CorsLayer, Any};
 use axum::extract::Path;
 use chrono::Datelike;
 use axum::http::{Method, HeaderValue};
+use std::sync::Arc;
 use std::time::Duration;
+use uuid::Uuid;
 
 #[derive(serde::Deserialize, serde::Serialize)]
 struct InsertCourseLinkRequest { 
@@ -28,39 +60,82 @@ struct InsertCourseLinkRequest {
 
 #[tokio::main]
 async fn main() {
-    // Initialize courses if table is empty
-    if let Err(e) = course_initialization::initialize_courses_if_empty().await {
-        eprintln!("Failed to initialize courses: {}", e);
+    let pool = establish_pool().expect("DATABASE_URL must be set");
+
+    // Sync courses with the catalog, then index them for suggestions
+    let course_suggestions = Arc::new(course_suggestions::CourseSuggestions::default());
+    match get_connection(&pool).await {
+        Ok(mut conn) => {
+            match course_initialization::sync_courses_from_json(&mut conn) {
+                Ok(report) => println!("Synced courses: {} added, {} changed, {} removed, {} kept admin edits", report.added.len(), report.changed.len(), report.removed.len(), report.kept_edits.len()),
+                Err(e) => eprintln!("Failed to sync courses: {}", e)
+            }
+            if let Err(e) = course_suggestions.refresh(&mut conn) {
+                eprintln!("Failed to index courses for suggestions: {}", e);
+            }
+        },
+        Err(e) => eprintln!("Failed to sync courses: {}", e.0)
     }
 
-    // Initialize token cache
-    if let Err(e) = authentication::get_token_cache().await {
-        eprintln!("Failed to initialize token cache: {}", e);
-    }
+    let state = AppState {
+        pool,
+        storage: storage::storage_backend_from_env().await,
+        upload_concurrency: dotenvy::var("UPLOAD_CONCURRENCY").ok().and_then(|v| v.parse().ok()).unwrap_or(4),
+        mailer: mail::mailer_from_env(),
+        tokens: Arc::new(accounts::TokenKeys::from_env()),
+        report_hide_threshold: reports::report_hide_threshold_from_env(),
+        course_suggestions
+    };
 
-    println!("Initialized token cache for buckets");
+    link_health::spawn_link_health_checker(state.pool.clone(), Arc::new(link_health::ReqwestHttpClient::new()), link_health::link_health_config_from_env());
 
-    let mut app = Router::new()
+    let mut router = Router::new()
         .route("/v1/courses", get(get_courses))
+        .route("/v1/courses/suggest", get(course_suggestions::suggest_courses))
+        .route("/v1/faculties", get(faculties::get_faculties))
+        .route("/v1/search", get(search::search))
+        .route("/v1/courses/:course_id/ratings", get(ratings::get_course_ratings).post(ratings::rate_course))
         .route("/v1/course_details/:course_id", get(get_course_details))
-        .route("/v1/course_resource/:course_id", post(insert_course_resource))
-        .route("/v1/course_link/:course_id", post(insert_course_link))
+        // POST takes the id of the course to add the resource to, PATCH and DELETE take the resource id
+        .route("/v1/course_resource/:id", post(insert_course_resource).patch(edit_course_resource).delete(delete_course_resource))
+        .route("/v1/course_resource/:id/files", post(insert_course_resource_files))
+        .route("/v1/course_resource/:id/vote", post(votes::vote_on_course_resource))
+        .route("/v1/course_resource/:id/comments", get(comments::get_resource_comments).post(comments::post_resource_comment))
+        .route("/v1/course_resource/:id/comments/:comment_id", patch(comments::edit_resource_comment).delete(comments::delete_resource_comment))
+        .route("/v1/course_resource/:id/files/:file_id", delete(delete_course_resource_file))
+        // POST takes the id of the course to add the link to, PATCH and DELETE take the link id
+        .route("/v1/course_link/:id", post(insert_course_link).patch(course_links::edit_course_link).delete(course_links::delete_course_link))
+        .route("/v1/reports", post(reports::create_report))
+        .route("/v1/auth/me", get(accounts::get_current_user))
+        .nest("/v1/admin", admin::router())
+        // Everything above needs a signed in user for anything other than GET
+        .route_layer(middleware::from_fn_with_state(state.clone(), accounts::authenticate))
+        .route("/v1/auth/request_code", post(accounts::request_verification_code))
+        .route("/v1/auth/verify", post(accounts::verify_email_code));
+
+    if state.storage.serves_files() {
+        router = router.route("/v1/files/*key", get(get_storage_file));
+    }
+
+    let mut app = router
         .fallback(fallback)
-        .layer(DefaultBodyLimit::max(1024 * 1024 * 1024));
+        .layer(DefaultBodyLimit::max(1024 * 1024 * 1024))
+        .with_state(state);
 
     if dotenvy::var("LOCAL_DEV_DEPLOYMENT").is_ok() {
         println!("Local dev deployment");
         let cors = CorsLayer::new()
             .allow_origin(Any)
-            .allow_headers(Any)
+            // Browsers never let `*` cover Authorization, so the headers have to be listed
+            .allow_headers([AUTHORIZATION, CONTENT_TYPE])
             .allow_methods(Any);
         app = app.layer(cors).layer(DefaultBodyLimit::max(1024 * 1024 * 1024));
     } else {
         // Production CORS configuration
         let cors = CorsLayer::new()
             .allow_origin("https://gjufiles.com".parse::<HeaderValue>().unwrap())
-            .allow_methods([Method::GET, Method::POST])
-            .allow_headers(Any)
+            .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+            .allow_headers([AUTHORIZATION, CONTENT_TYPE])
             .max_age(Duration::from_secs(3600));
         app = app.layer(cors).layer(DefaultBodyLimit::max(1024 * 1024 * 1024));
     }
@@ -77,127 +152,387 @@ async fn fallback(uri: axum::http::Uri) -> impl axum::response::IntoResponse {
     (axum::http::StatusCode::NOT_FOUND, "Not Found")
 }
 
-async fn insert_course_link(Path(course_id): Path<String>, Json(payload): Json<InsertCourseLinkRequest> ) -> Result<impl IntoResponse, StatusCode> {
-    let conn = &mut establish_connection().unwrap();
-    if payload.url.is_empty() {
-        return Ok((StatusCode::BAD_REQUEST, Json(ErrorResponse { error: "URL can't be empty".to_string() })).into_response());
-    }
+async fn insert_course_link(AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Path(course_id): Path<String>, Json(payload): Json<InsertCourseLinkRequest> ) -> Result<impl IntoResponse, StatusCode> {
+    let title = match course_links::validate_link_title(&payload.title) {
+        Ok(title) => title,
+        Err(e) => return Ok(bad_request(&e))
+    };
 
-    let url_regex = Regex::new(r"https?://(www\.)?[-a-zA-Z0-9@:%._\+~#=]{2,256}(\.[a-z]{2,4})?\b([-a-zA-Z0-9@:%_\+.~#?&//=]*)").unwrap();
-    if !url_regex.is_match(&payload.url) {
-        return Ok((StatusCode::BAD_REQUEST, Json(ErrorResponse { error: "Invalid URL".to_string() })).into_response());
+    let url = match link_validation::validate_link_url(&payload.url) {
+        Ok(url) => url,
+        Err(e) => return Ok(bad_request(&e))
+    };
+
+    match insert_course_link_into_db(&mut conn, title, url, course_id, Some(user.user_id)) {
+        Ok(link) => Ok(Json(link).into_response()),
+        Err(e) => Ok(course_links::course_link_error_response(e))
     }
+}
 
-    match insert_course_link_into_db(conn, payload.title, payload.url, course_id) {
-        Ok(_) => Ok(StatusCode::OK.into_response()),
+async fn get_storage_file(State(state): State<AppState>, Path(key): Path<String>) -> Result<impl IntoResponse, StatusCode> {
+    let key = key.trim_start_matches('/');
+    match state.storage.get(key).await {
+        Ok(data) => Ok(([(CONTENT_TYPE, file_content_type(key))], data).into_response()),
+        Err(StorageError::NotFound(_)) | Err(StorageError::InvalidKey(_)) => {
+            Ok((StatusCode::NOT_FOUND, Json(ErrorResponse { error: format!("File {} not found", key) })).into_response())
+        },
         Err(e) => Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })).into_response())
     }
 }
 
-pub async fn insert_course_resource(
-    Path(course_id): Path<String>,
-    mut multipart: Multipart,
-) -> Result<impl IntoResponse, StatusCode> {
-    // Get the JSON part first
+fn bad_request(error: &str) -> Response {
+    (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: error.to_string() })).into_response()
+}
+
+fn validate_academic_year(academic_year: i32) -> Result<(), String> {
+    if academic_year > chrono::Utc::now().year() {
+        return Err("Academic year can't be greater than the current year".to_string());
+    }
+
+    if academic_year < 2000 {
+        return Err("Academic year can't be less than 2000".to_string());
+    }
+
+    Ok(())
+}
+
+/// Checks the metadata of a resource being uploaded
+fn validate_course_resource_metadata(payload: InsertCourseResource) -> Result<InsertCourseResource, String> {
+    if payload.title.replace(" ", "").is_empty() || payload.course_id.replace(" ", "").is_empty() {
+        return Err("Title / course id can't be empty".to_string());
+    }
+
+    validate_academic_year(payload.academic_year)?;
+
+    Ok(payload)
+}
+
+/// Turns the optional filters of the course details query into a year range
+fn validate_course_resource_filters(query: GetCourseDetailsQuery) -> Result<CourseResourceFilters, String> {
+    if query.academic_year.is_some() && (query.academic_year_from.is_some() || query.academic_year_to.is_some()) {
+        return Err("Use either academic_year or academic_year_from / academic_year_to, not both".to_string());
+    }
+
+    let academic_year_from = query.academic_year.or(query.academic_year_from);
+    let academic_year_to = query.academic_year.or(query.academic_year_to);
+    if let (Some(from), Some(to)) = (academic_year_from, academic_year_to) {
+        if from > to {
+            return Err("academic_year_from can't be after academic_year_to".to_string());
+        }
+    }
+
+    Ok(CourseResourceFilters {
+        sort: query.sort,
+        semester: query.semester,
+        academic_year_from,
+        academic_year_to,
+        issolved: query.issolved,
+        q: query.q.map(|q| q.trim().to_string()).filter(|q| !q.is_empty()),
+        page: query.page
+    })
+}
+
+/// Checks the page size and cursor of the course list, and picks its sort
+fn validate_courses_page(query: &GetCoursesQuery) -> Result<CoursesPage, String> {
+    let page_size = query.page_size.unwrap_or(courses_per_page());
+    if !(1..=MAX_COURSES_PER_PAGE).contains(&page_size) {
+        return Err(format!("page_size must be between 1 and {}", MAX_COURSES_PER_PAGE));
+    }
+
+    let searching = query.search.as_deref().and_then(CourseSearch::new).is_some();
+    let sort = query.sort.unwrap_or(if searching { CourseSort::Relevance } else { CourseSort::CourseId });
+    if sort == CourseSort::Relevance && !searching {
+        return Err("Sorting by relevance needs a search term".to_string());
+    }
+
+    let cursor = match &query.cursor {
+        Some(cursor) => {
+            if query.page.is_some() {
+                return Err("Use either cursor or page, not both".to_string());
+            }
+
+            let Some(cursor) = CourseCursor::decode(cursor) else {
+                return Err("Invalid cursor".to_string());
+            };
+            if cursor.sort() != sort {
+                return Err("The cursor is from a different sort".to_string());
+            }
+            Some(cursor)
+        },
+        None => None
+    };
+
+    Ok(CoursesPage { sort, page_size, cursor, page: query.page })
+}
+
+/// Same checks as `validate_course_resource_metadata`, for the fields being edited
+fn validate_course_resource_edit(edit: EditCourseResource) -> Result<CourseResourceChangeset, String> {
+    let mut changes = CourseResourceChangeset::default();
+
+    if let Some(title) = edit.title {
+        if title.replace(" ", "").is_empty() {
+            return Err("Title can't be empty".to_string());
+        }
+        changes.title = Some(title);
+    }
+
+    if let Some(subtitle) = edit.subtitle {
+        changes.subtitle = Some(if subtitle.trim().is_empty() { None } else { Some(subtitle) });
+    }
+
+    changes.resource_type = edit.resource_type;
+    changes.semester = edit.semester;
+
+    if let Some(academic_year) = edit.academic_year {
+        validate_academic_year(academic_year)?;
+        changes.academic_year = Some(academic_year);
+    }
+
+    changes.issolved = edit.issolved;
+
+    if changes.title.is_none() && changes.subtitle.is_none() && changes.resource_type.is_none()
+        && changes.semester.is_none() && changes.academic_year.is_none() && changes.issolved.is_none() {
+        return Err("Nothing to update".to_string());
+    }
+
+    Ok(changes)
+}
+
+// Reads the multipart body of a resource upload, streaming every file to storage as it arrives
+async fn read_course_resource_upload(multipart: &mut Multipart, uploads: &mut ResourceFileUploads) -> Result<InsertCourseResource, Response> {
     let mut payload: Option<InsertCourseResource> = None;
-    let mut files: Vec<CourseResourceUploadFile> = Vec::new();
 
-    while let Some(field) = multipart.next_field().await.map_err(|_| StatusCode::BAD_REQUEST)? {
+    while let Some(field) = multipart.next_field().await.map_err(|_| StatusCode::BAD_REQUEST.into_response())? {
         let name = field.name().unwrap_or("").to_string();
-        
+
         if name == "metadata" {
-            let data = field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?;
-            payload = Some(serde_json::from_slice(&data).map_err(|_| StatusCode::BAD_REQUEST)?);
+            let data = field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST.into_response())?;
+            let metadata = serde_json::from_slice(&data).map_err(|e| bad_request(&format!("Invalid metadata: {}", e)))?;
+            payload = Some(validate_course_resource_metadata(metadata).map_err(|e| bad_request(&e))?);
         } else if name == "files" {
-            let file_name = field.file_name().ok_or(StatusCode::BAD_REQUEST)?.to_string();
-            println!("Entering file {}", file_name);
-            let file_data = field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?;
-            println!("Got data of file {}", file_name);
-            let file_data_vec = file_data.to_vec();
-            files.push(CourseResourceUploadFile { filename: file_name.clone(), data: file_data_vec });
-            println!("File uploaded: {}", file_name);
+            let file_name = field.file_name().ok_or(StatusCode::BAD_REQUEST.into_response())?.to_string();
+            uploads.upload(&file_name, field).await.map_err(|e| bad_request(&e.to_string()))?;
         }
     }
 
-    if files.is_empty() {
-        return Ok((StatusCode::BAD_REQUEST, Json(ErrorResponse { 
-            error: "User must upload at least one file".to_string() 
-        })).into_response());
+    if uploads.is_empty() {
+        return Err(bad_request("User must upload at least one file"));
     }
 
-    let payload = match payload {
-        Some(p) => p,
-        None => return Ok((StatusCode::BAD_REQUEST, Json(ErrorResponse { 
-            error: "Payload is required".to_string() 
-        })).into_response())
+    payload.ok_or(bad_request("Payload is required"))
+}
+
+pub async fn insert_course_resource(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(course_id): Path<String>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, StatusCode> {
+    let mut uploads = ResourceFileUploads::new(state.storage.clone(), course_id.clone(), Uuid::new_v4(), state.upload_concurrency);
+    let resource_id = uploads.resource_id();
+
+    let payload = match read_course_resource_upload(&mut multipart, &mut uploads).await {
+        Ok(payload) => payload,
+        Err(response) => {
+            uploads.abort().await;
+            return Ok(response);
+        }
     };
 
-    println!("{:?}", payload);
+    let files = match uploads.finish().await {
+        Ok(files) => files,
+        Err(e) => return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { 
+            error: e.to_string() 
+        })).into_response())
+    };
 
+    let mut conn = match get_connection(&state.pool).await {
+        Ok(conn) => conn,
+        Err(e) => {
+            delete_resource_files_from_storage(state.storage.as_ref(), &course_id, &files).await;
+            return Ok(e.into_response());
+        }
+    };
 
-    let sem = match payload.semester.to_lowercase().as_str() {
-        "first" => "First".to_string(),
-        "second" => "Second".to_string(),
-        "summer" => "Summer".to_string(),
-        _ => return Ok((StatusCode::BAD_REQUEST, Json(ErrorResponse { 
-            error: "Invalid semester".to_string() 
+    match insert_course_resource_into_db(&mut conn, state.storage.as_ref(), resource_id, course_id, payload, files, Some(user.user_id)).await {
+        Ok(resource) => Ok(Json(resource).into_response()),
+        Err(e) => Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { 
+            error: e.to_string() 
         })).into_response())
+    }
+}
+
+// Resources can only be changed by whoever uploaded them, moderators go through the audited `/v1/admin` routes
+fn check_course_resource_owner(resource: &CourseResource, user: &User) -> Result<(), Response> {
+    if resource.uploader_id != Some(user.user_id) {
+        return Err(error_response(StatusCode::FORBIDDEN, "Only the uploader can change this resource"));
+    }
+
+    Ok(())
+}
+
+fn check_course_resource_not_hidden(resource: &CourseResource) -> Result<(), Response> {
+    if resource.is_hidden {
+        return Err(error_response(StatusCode::FORBIDDEN, "This resource was hidden by moderation and can't be changed"));
+    }
+
+    Ok(())
+}
+
+fn find_owned_course_resource(conn: &mut PgConnection, resource_id: Uuid, user: &User) -> Result<CourseResource, Response> {
+    let resource = match get_course_resource_from_db(conn, resource_id) {
+        Ok(resource) => resource,
+        Err(diesel::result::Error::NotFound) => {
+            return Err(error_response(StatusCode::NOT_FOUND, format!("Resource with id {} not found", resource_id)));
+        },
+        Err(e) => return Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
     };
 
-    if payload.title.replace(" ", "").is_empty() || payload.course_id.replace(" ", "").is_empty() {
-        return Ok((StatusCode::BAD_REQUEST, Json(ErrorResponse { 
-            error: "Title / course id can't be empty".to_string() 
-        })).into_response());
+    check_course_resource_owner(&resource, user)?;
+    Ok(resource)
+}
+
+async fn edit_course_resource(AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Path(resource_id): Path<Uuid>, Json(payload): Json<EditCourseResource>) -> Result<impl IntoResponse, StatusCode> {
+    let changes = match validate_course_resource_edit(payload) {
+        Ok(changes) => changes,
+        Err(e) => return Ok(bad_request(&e))
+    };
+
+    let resource = match find_owned_course_resource(&mut conn, resource_id, &user) {
+        Ok(resource) => resource,
+        Err(response) => return Ok(response)
+    };
+
+    if let Err(response) = check_course_resource_not_hidden(&resource) {
+        return Ok(response);
+    }
+
+    match update_course_resource_in_db(&mut conn, resource_id, changes) {
+        Ok(resource) => Ok(Json(resource).into_response()),
+        Err(diesel::result::Error::NotFound) => {
+            Ok((StatusCode::NOT_FOUND, Json(ErrorResponse { error: format!("Resource with id {} not found", resource_id) })).into_response())
+        },
+        Err(e) => Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })).into_response())
+    }
+}
+
+async fn delete_course_resource(State(state): State<AppState>, AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Path(resource_id): Path<Uuid>) -> Result<impl IntoResponse, StatusCode> {
+    if let Err(response) = find_owned_course_resource(&mut conn, resource_id, &user) {
+        return Ok(response);
+    }
+
+    // The rows go first, a leftover object is harmless but a row pointing to a deleted object isn't
+    match delete_course_resource_from_db(&mut conn, resource_id) {
+        Ok((resource, files)) => {
+            delete_resource_files_from_storage(state.storage.as_ref(), &resource.course_id, &files).await;
+            Ok(StatusCode::OK.into_response())
+        },
+        Err(diesel::result::Error::NotFound) => {
+            Ok((StatusCode::NOT_FOUND, Json(ErrorResponse { error: format!("Resource with id {} not found", resource_id) })).into_response())
+        },
+        Err(e) => Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })).into_response())
     }
+}
 
-    println!("Reached 2");
+// Reads a multipart body made up of only `files` fields, streaming each one to storage
+async fn read_course_resource_files(multipart: &mut Multipart, uploads: &mut ResourceFileUploads) -> Result<(), Response> {
+    while let Some(field) = multipart.next_field().await.map_err(|_| StatusCode::BAD_REQUEST.into_response())? {
+        if field.name() != Some("files") {
+            continue;
+        }
 
-    if payload.resource_type != 0 && payload.resource_type != 1 {
-        return Ok((StatusCode::BAD_REQUEST, Json(ErrorResponse { 
-            error: "Invalid resource type (Must be either 0 for Notes, or 1 for Exams)".to_string() 
-        })).into_response());
+        let file_name = field.file_name().ok_or(StatusCode::BAD_REQUEST.into_response())?.to_string();
+        uploads.upload(&file_name, field).await.map_err(|e| bad_request(&e.to_string()))?;
     }
 
-    if payload.academic_year > chrono::Utc::now().year() {
-        return Ok((StatusCode::BAD_REQUEST, Json(ErrorResponse { 
-            error: "Academic year can't be greater than the current year".to_string() 
-        })).into_response());
+    if uploads.is_empty() {
+        return Err(bad_request("User must upload at least one file"));
     }
 
-    if payload.academic_year < 2000 {
-        return Ok((StatusCode::BAD_REQUEST, Json(ErrorResponse { 
-            error: "Academic year can't be less than 2000".to_string() 
-        })).into_response());
+    Ok(())
+}
+
+async fn insert_course_resource_files(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(resource_id): Path<Uuid>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, StatusCode> {
+    let existing = {
+        let mut conn = match get_connection(&state.pool).await {
+            Ok(conn) => conn,
+            Err(e) => return Ok(e.into_response())
+        };
+
+        match get_course_resource_with_files_from_db(&mut conn, resource_id) {
+            Ok(existing) => existing,
+            Err(diesel::result::Error::NotFound) => {
+                return Ok((StatusCode::NOT_FOUND, Json(ErrorResponse { error: format!("Resource with id {} not found", resource_id) })).into_response());
+            },
+            Err(e) => return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })).into_response())
+        }
+    };
+
+    if let Err(response) = check_course_resource_owner(&existing.resource_info, &user).and_then(|_| check_course_resource_not_hidden(&existing.resource_info)) {
+        return Ok(response);
     }
 
-    let conn = &mut establish_connection().unwrap();
-    match insert_course_resource_into_db(
-        conn, 
-        payload.title, 
-        payload.subtitle, 
-        course_id, 
-        payload.resource_type, 
-        sem, 
-        payload.academic_year, 
-        payload.issolved,
-        files
-    ).await {
-        Ok(resource) => Ok(Json(resource).into_response()),
-        Err(e) => Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { 
-            error: e.to_string() 
-        })).into_response())
+    let course_id = existing.resource_info.course_id.clone();
+    let mut uploads = ResourceFileUploads::new(state.storage.clone(), course_id.clone(), resource_id, state.upload_concurrency)
+        .with_existing_file_names(existing.files.into_iter().map(|file| file.file_name));
+
+    if let Err(response) = read_course_resource_files(&mut multipart, &mut uploads).await {
+        uploads.abort().await;
+        return Ok(response);
+    }
+
+    let files = match uploads.finish().await {
+        Ok(files) => files,
+        Err(e) => return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })).into_response())
+    };
+
+    let mut conn = match get_connection(&state.pool).await {
+        Ok(conn) => conn,
+        Err(e) => {
+            delete_resource_files_from_storage(state.storage.as_ref(), &course_id, &files).await;
+            return Ok(e.into_response());
+        }
+    };
+
+    match insert_course_resource_files_into_db(&mut conn, state.storage.as_ref(), &course_id, files).await {
+        Ok(files) => Ok(Json(files).into_response()),
+        Err(e) => Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })).into_response())
+    }
+}
+
+async fn delete_course_resource_file(State(state): State<AppState>, AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Path((resource_id, file_id)): Path<(Uuid, Uuid)>) -> Result<impl IntoResponse, StatusCode> {
+    if let Err(response) = find_owned_course_resource(&mut conn, resource_id, &user) {
+        return Ok(response);
+    }
+
+    match delete_course_resource_file_from_db(&mut conn, resource_id, file_id) {
+        Ok((course_id, file)) => {
+            delete_resource_files_from_storage(state.storage.as_ref(), &course_id, &[file]).await;
+            Ok(StatusCode::OK.into_response())
+        },
+        Err(DeleteCourseResourceFileError::Database(diesel::result::Error::NotFound)) => {
+            Ok((StatusCode::NOT_FOUND, Json(ErrorResponse { error: format!("File with id {} not found in resource {}", file_id, resource_id) })).into_response())
+        },
+        Err(e @ DeleteCourseResourceFileError::LastFile) => Ok(bad_request(&e.to_string())),
+        Err(e) => Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })).into_response())
     }
 }
 
-async fn get_course_details(course_id: Path<String>, query: Query<GetCourseDetailsQuery>) -> Result<impl IntoResponse, StatusCode> {
-    let conn = &mut establish_connection().unwrap();
+async fn get_course_details(DbConn(mut conn): DbConn, course_id: Path<String>, query: Query<GetCourseDetailsQuery>) -> Result<impl IntoResponse, StatusCode> {
     let id = course_id.0.clone();
     let resource_type = query.0.resource_type;
-    if resource_type != 0 && resource_type != 1 {
-        return Ok((StatusCode::BAD_REQUEST, Json(ErrorResponse { error: "Invalid resource type (Must be either 0 for Notes, or 1 for Exams)".to_string() })).into_response());
-    }
+    let link_kind = query.0.link_kind;
+    let filters = match validate_course_resource_filters(query.0) {
+        Ok(filters) => filters,
+        Err(e) => return Ok(bad_request(&e))
+    };
 
-    let course_details = get_course_details_from_db(conn, id, resource_type);
+    let course_details = get_course_details_from_db(&mut conn, id, resource_type, &filters, link_kind);
     match course_details {
         Ok(course_details) => {
             Ok(Json(course_details).into_response())
@@ -212,11 +547,14 @@ async fn get_course_details(course_id: Path<String>, query: Query<GetCourseDetai
     }
 }
 
-async fn get_courses(query: Query<GetCoursesQuery>) -> Result<impl IntoResponse, StatusCode> {
-    let conn = &mut establish_connection().unwrap();
+async fn get_courses(DbConn(mut conn): DbConn, query: Query<GetCoursesQuery>) -> Result<impl IntoResponse, StatusCode> {
     let get_courses_q = query.0;
+    let page = match validate_courses_page(&get_courses_q) {
+        Ok(page) => page,
+        Err(e) => return Ok(bad_request(&e))
+    };
 
-    match get_courses_from_db(conn, get_courses_q.faculty, get_courses_q.search, get_courses_q.page) { 
+    match get_courses_from_db(&mut conn, get_courses_q.faculty, get_courses_q.search, &page) { 
         Ok(courses) => { 
             Ok(Json(courses).into_response())
         },
@@ -1,4 +1,10 @@
-use crate::schema::{courses, course_resources, course_resource_files, course_resource_links};
+use crate::faculties::Faculties;
+use crate::link_kinds::LinkKind;
+use crate::resource_types::ResourceType;
+use crate::semesters::Semester;
+use crate::schema::{admin_audit_log, courses, course_ratings, course_resources, course_resource_files, course_resource_links, email_verification_codes, faculties, reports, resource_comments, resource_votes, users};
+use axum::{http::StatusCode, response::{IntoResponse, Response}, Json};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::Utc;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -11,7 +17,8 @@ use uuid::Uuid;
 pub struct Course {    
     pub course_id: String, /* CS116 */
     pub course_name: String, /* Computing Fundamentals */
-    pub course_faculty: i16
+    pub course_faculty: Faculties,
+    pub is_active: bool /* false once the course is removed from the catalog */
 }
 
 #[derive(Queryable, Selectable, Serialize, Deserialize, Insertable)]
@@ -24,14 +31,18 @@ pub struct CourseResource {
     pub resource_id: Uuid,
     pub course_id: String,
 
-    pub resource_type: i16, /* 0 = Notes, 1 = Exams */
+    pub resource_type: ResourceType,
 
     #[diesel(sql_type = diesel::sql_types::Timestamptz)]
     pub dateuploaded: chrono::DateTime<Utc>,
     
-    pub semester: String,
+    pub semester: Semester,
     pub academic_year: i32,
-    pub issolved: bool
+    pub issolved: bool,
+
+    pub uploader_id: Option<Uuid>,
+    pub is_hidden: bool,
+    pub score: i32 /* Upvotes minus downvotes */
 }
 
 #[derive(Deserialize, Serialize, Queryable, Debug)]
@@ -39,15 +50,33 @@ pub struct InsertCourseResource {
     pub title: String,
     pub subtitle: Option<String>,
     pub course_id: String,
-    pub resource_type: i16,
-    pub semester: String,
+    pub resource_type: ResourceType,
+    pub semester: Semester,
     pub academic_year: i32,
     pub issolved: bool,
 }
 
-#[derive(Deserialize, Serialize, Queryable)]
-pub struct InsertCourseResourceFile {
-    pub file_name: String,
+/// Body of `PATCH /v1/course_resource/:resource_id`, only the fields that are present get updated.
+/// An empty subtitle removes it
+#[derive(Deserialize, Debug)]
+pub struct EditCourseResource {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    pub resource_type: Option<ResourceType>,
+    pub semester: Option<Semester>,
+    pub academic_year: Option<i32>,
+    pub issolved: Option<bool>,
+}
+
+#[derive(AsChangeset, Default)]
+#[diesel(table_name = course_resources)]
+pub struct CourseResourceChangeset {
+    pub title: Option<String>,
+    pub subtitle: Option<Option<String>>,
+    pub resource_type: Option<ResourceType>,
+    pub semester: Option<Semester>,
+    pub academic_year: Option<i32>,
+    pub issolved: Option<bool>,
 }
 
 #[derive(Queryable, Selectable, Serialize, Insertable)]
@@ -57,13 +86,33 @@ pub struct CourseResourceFile {
     pub file_id: Uuid,
     pub file_name: String,
     pub file_url: String,
-    pub resource_id: Uuid
+    pub resource_id: Uuid,
+    pub is_hidden: bool
+}
+
+/// Averages of a course's ratings, the averages are `None` while nobody has rated it
+#[derive(Serialize, Default, Clone)]
+pub struct CourseRatingSummary {
+    pub rating_count: i64,
+    pub average_difficulty: Option<f64>,
+    pub average_workload: Option<f64>,
+    pub average_usefulness: Option<f64>
+}
+
+/// A course along with the summary of its ratings
+#[derive(Serialize)]
+pub struct CourseMetadata {
+    #[serde(flatten)]
+    pub course: Course,
+    pub ratings: CourseRatingSummary
 }
 
 #[derive(Serialize)]
 pub struct GetCoursesResponse { 
-    pub courses: Vec<Course>,
-    pub total_courses: i64
+    pub courses: Vec<CourseMetadata>,
+    pub total_courses: i64,
+    /// Pass as `cursor` to get the courses after this page, `None` on the last page
+    pub next_cursor: Option<String>
 }
 
 #[derive(Serialize)]
@@ -71,45 +120,581 @@ pub struct ErrorResponse {
    pub  error: String,
 }
 
+/// Shorthand for responding with an `ErrorResponse`
+pub fn error_response(status: StatusCode, error: impl Into<String>) -> Response {
+    (status, Json(ErrorResponse { error: error.into() })).into_response()
+}
+
+/// A course suggested while typing in the search box
+#[derive(Serialize, Clone)]
+pub struct CourseSuggestion {
+    pub course_id: String,
+    pub course_name: String
+}
+
+#[derive(Deserialize)]
+pub struct SuggestCoursesQuery {
+    #[serde(default)]
+    pub q: String,
+    pub limit: Option<usize>
+}
+
 #[derive(Deserialize)]
 pub struct GetCoursesQuery { 
-    pub faculty: Option<i16>,
+    pub faculty: Option<Faculties>,
     pub search: Option<String>, /* searchTerm */
+    pub sort: Option<CourseSort>,
+    pub page_size: Option<i64>,
+    pub cursor: Option<String>, /* next_cursor of the previous page, instead of page */
     pub page: Option<i64>
 }
 
+/// Order of the course list. Most relevant first when searching, by course id otherwise
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CourseSort {
+    CourseId,
+    Name,
+    /// Most resources first
+    Resources,
+    Relevance
+}
+
+/// Where a page of courses left off, sent to clients as an opaque string
+#[derive(Serialize, Deserialize)]
+pub struct CourseCursor {
+    pub course_id: String,
+    pub key: CourseCursorKey
+}
+
+/// The sort key of the last course of a page, course ids break ties
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CourseCursorKey {
+    CourseId,
+    Name(String),
+    Resources(i64),
+    Relevance(f64)
+}
+
+impl CourseCursor {
+    pub fn sort(&self) -> CourseSort {
+        match self.key {
+            CourseCursorKey::CourseId => CourseSort::CourseId,
+            CourseCursorKey::Name(_) => CourseSort::Name,
+            CourseCursorKey::Resources(_) => CourseSort::Resources,
+            CourseCursorKey::Relevance(_) => CourseSort::Relevance
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let json = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+/// Validated sort and pagination of the course list
+pub struct CoursesPage {
+    pub sort: CourseSort,
+    pub page_size: i64,
+    pub cursor: Option<CourseCursor>,
+    pub page: Option<i64>
+}
+
+/// Order of the resources in the course details, newest first by default
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceSort {
+    Score,
+    #[default]
+    Newest,
+    AcademicYear,
+    Title
+}
+
+/// Query of `GET /v1/course_details/:course_id`, every filter is optional.
+/// `academic_year` matches a single year, `academic_year_from` and `academic_year_to` a range (inclusive)
 #[derive(Deserialize)]
 pub struct GetCourseDetailsQuery {
-    pub resource_type: i16
+    pub resource_type: ResourceType,
+    #[serde(default)]
+    pub sort: ResourceSort,
+    pub semester: Option<Semester>,
+    pub academic_year: Option<i32>,
+    pub academic_year_from: Option<i32>,
+    pub academic_year_to: Option<i32>,
+    pub issolved: Option<bool>,
+    pub q: Option<String>, /* Searched for in the title and subtitle */
+    pub page: Option<i64>,
+    pub link_kind: Option<LinkKind> /* Only return links of this kind */
+}
+
+/// Validated filters for the resources of a course
+pub struct CourseResourceFilters {
+    pub sort: ResourceSort,
+    pub semester: Option<Semester>,
+    pub academic_year_from: Option<i32>,
+    pub academic_year_to: Option<i32>,
+    pub issolved: Option<bool>,
+    pub q: Option<String>,
+    pub page: Option<i64>
 }
 
 #[derive(Serialize)]
 pub struct CourseDetailsResourceResponse {
     pub resource_info: CourseResource,
-    pub files: Vec<CourseResourceFile>
+    pub files: Vec<CourseResourceFile>,
+    pub comment_count: i64
 }
 
 #[derive(Serialize)]
 pub struct CourseDetailsLinkResponse {
+    pub link_id: Uuid,
     pub title: String,
-    pub url: String
+    pub url: String,
+    pub uploader_id: Option<Uuid>,
+    pub date_added: chrono::DateTime<Utc>,
+    pub link_kind: LinkKind
+}
+
+impl From<CourseResourceLink> for CourseDetailsLinkResponse {
+    fn from(link: CourseResourceLink) -> Self {
+        CourseDetailsLinkResponse { link_id: link.link_id, title: link.link_title, url: link.link_url, uploader_id: link.uploader_id, date_added: link.date_added, link_kind: link.link_kind }
+    }
 }
 
 #[derive(Serialize)]
 pub struct CourseDetails {
-    pub metadata: Course,
+    pub metadata: CourseMetadata,
     pub resources: Vec<CourseDetailsResourceResponse>,
     pub links: Vec<CourseDetailsLinkResponse>,
+    /// How many resources match the filters, for paginating `resources`
+    pub total_resources: i64,
     pub no_notes: i64,
     pub no_exams: i64
 }
 
-#[derive(Deserialize, Serialize, Insertable, Queryable, Debug)]
+#[derive(Deserialize, Serialize, Insertable, Queryable, Selectable, Debug)]
 #[diesel(table_name = course_resource_links)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct CourseResourceLink {
     pub link_id: Uuid,
     pub link_title: String,
     pub link_url: String,
-    pub course_id: String
-}
\ No newline at end of file
+    pub course_id: String,
+    pub uploader_id: Option<Uuid>,
+    pub is_hidden: bool,
+    pub date_added: chrono::DateTime<Utc>,
+    pub link_kind: LinkKind,
+    /// Set by the link health checker
+    pub last_checked: Option<chrono::DateTime<Utc>>,
+    pub last_status: Option<i16>,
+    pub consecutive_failures: i32
+}
+
+/// Body of `PATCH /v1/course_link/:link_id`, only the fields that are present get updated
+#[derive(Deserialize)]
+pub struct EditCourseLink {
+    pub title: Option<String>,
+    pub url: Option<String>
+}
+
+#[derive(AsChangeset, Default)]
+#[diesel(table_name = course_resource_links)]
+pub struct CourseLinkChangeset {
+    pub link_title: Option<String>,
+    pub link_url: Option<String>,
+    pub link_kind: Option<LinkKind>,
+    /// A new URL hasn't been checked yet
+    pub last_checked: Option<Option<chrono::DateTime<Utc>>>,
+    pub last_status: Option<Option<i16>>,
+    pub consecutive_failures: Option<i32>
+}
+
+#[derive(Queryable, Selectable, Serialize, Insertable, Debug, Clone)]
+#[diesel(table_name = users)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct User {
+    pub user_id: Uuid,
+    pub email: String,
+    pub created_at: chrono::DateTime<Utc>,
+    pub role: String, /* "user" or "admin" */
+    pub is_banned: bool
+}
+
+impl User {
+    pub const ADMIN_ROLE: &'static str = "admin";
+
+    pub fn is_admin(&self) -> bool {
+        self.role == Self::ADMIN_ROLE
+    }
+}
+
+#[derive(Queryable, Selectable, Insertable, AsChangeset)]
+#[diesel(table_name = email_verification_codes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct EmailVerificationCode {
+    pub email: String,
+    pub code_hash: String,
+    pub created_at: chrono::DateTime<Utc>,
+    pub expires_at: chrono::DateTime<Utc>,
+    pub attempts: i32 /* Times this code was tried, it stops working after MAX_CODE_ATTEMPTS */
+}
+
+#[derive(Deserialize)]
+pub struct RequestVerificationCode {
+    pub email: String
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailCode {
+    pub email: String,
+    pub code: String
+}
+
+#[derive(Serialize)]
+pub struct AuthResponse {
+    pub token: String,
+    pub user: User
+}
+#[derive(Queryable, Selectable, Serialize, Insertable)]
+#[diesel(table_name = admin_audit_log)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AdminAuditLogEntry {
+    pub audit_id: Uuid,
+    pub admin_id: Uuid,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub details: Option<String>,
+    pub created_at: chrono::DateTime<Utc>
+}
+
+#[derive(Deserialize)]
+pub struct AdminPageQuery {
+    pub page: Option<i64>
+}
+
+#[derive(Serialize)]
+pub struct AdminRecentUploadsResponse {
+    pub resources: Vec<CourseResource>,
+    pub links: Vec<CourseResourceLink>
+}
+
+#[derive(Deserialize)]
+pub struct SetHiddenRequest {
+    pub hidden: bool
+}
+
+#[derive(Deserialize)]
+pub struct BanUserRequest {
+    pub banned: bool,
+    pub reason: Option<String>
+}
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = courses)]
+pub struct EditCourse {
+    pub course_name: Option<String>,
+    pub course_faculty: Option<Faculties>
+}
+
+/// What a report is about
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportTargetType {
+    Resource,
+    File,
+    Link
+}
+
+impl ReportTargetType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportTargetType::Resource => "resource",
+            ReportTargetType::File => "file",
+            ReportTargetType::Link => "link"
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportReason {
+    WrongCourse,
+    Inappropriate,
+    Spam,
+    Copyright,
+    Broken,
+    Other
+}
+
+impl ReportReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportReason::WrongCourse => "wrong_course",
+            ReportReason::Inappropriate => "inappropriate",
+            ReportReason::Spam => "spam",
+            ReportReason::Copyright => "copyright",
+            ReportReason::Broken => "broken",
+            ReportReason::Other => "other"
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Serialize, Insertable)]
+#[diesel(table_name = reports)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Report {
+    pub report_id: Uuid,
+    pub reporter_id: Uuid,
+    pub target_type: String, /* "resource", "file" or "link" */
+    pub target_id: Uuid,
+    pub reason: String,
+    pub details: Option<String>,
+    pub status: String, /* "pending", "upheld" or "dismissed" */
+    pub created_at: chrono::DateTime<Utc>,
+    pub hid_target: bool /* Whether this report is the one that hid the item */
+}
+
+impl Report {
+    pub const PENDING: &'static str = "pending";
+    pub const UPHELD: &'static str = "upheld";
+    pub const DISMISSED: &'static str = "dismissed";
+}
+
+/// Body of `POST /v1/reports`
+#[derive(Deserialize)]
+pub struct CreateReport {
+    pub target_type: ReportTargetType,
+    pub target_id: Uuid,
+    pub reason: ReportReason,
+    pub details: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct CreateReportResponse {
+    pub report: Report,
+    /// Whether this report pushed the item over the threshold and it got hidden
+    pub target_hidden: bool
+}
+
+#[derive(Deserialize)]
+pub struct AdminReportsQuery {
+    pub status: Option<String>,
+    pub page: Option<i64>
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportDecision {
+    /// The reports are right, the item stays hidden
+    Uphold,
+    /// The reports are wrong. The item is shown again if the reports hid it,
+    /// but stays hidden if a moderator or the link checker hid it
+    Dismiss
+}
+
+/// Body of `POST /v1/admin/reports/:report_id/review`
+#[derive(Deserialize)]
+pub struct ReviewReport {
+    pub decision: ReportDecision
+}
+
+#[derive(Queryable, Selectable, Serialize, Insertable)]
+#[diesel(table_name = course_ratings)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CourseRating {
+    pub rating_id: Uuid,
+    pub course_id: String,
+    pub user_id: Uuid,
+    pub difficulty: i16, /* 1 = Easy, 5 = Very hard */
+    pub workload: i16, /* 1 = Light, 5 = Very heavy */
+    pub usefulness: i16, /* 1 = Useless, 5 = Very useful */
+    pub review: Option<String>,
+    pub created_at: chrono::DateTime<Utc>,
+    pub updated_at: chrono::DateTime<Utc>
+}
+
+/// Body of `POST /v1/courses/:course_id/ratings`, rating a course again replaces the previous rating
+#[derive(Deserialize)]
+pub struct RateCourse {
+    pub difficulty: i16,
+    pub workload: i16,
+    pub usefulness: i16,
+    pub review: Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct GetCourseRatingsQuery {
+    pub page: Option<i64>
+}
+
+#[derive(Serialize)]
+pub struct GetCourseRatingsResponse {
+    pub ratings: Vec<CourseRating>,
+    pub summary: CourseRatingSummary
+}
+
+#[derive(Queryable, Selectable, Serialize, Insertable)]
+#[diesel(table_name = resource_votes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ResourceVote {
+    pub resource_id: Uuid,
+    pub user_id: Uuid,
+    pub vote: i16, /* 1 = Upvote, -1 = Downvote */
+    pub created_at: chrono::DateTime<Utc>
+}
+
+/// Body of `POST /v1/course_resource/:resource_id/vote`, 1 to upvote, -1 to downvote and 0 to take the vote back
+#[derive(Deserialize)]
+pub struct VoteOnResource {
+    pub vote: i16
+}
+
+#[derive(Serialize)]
+pub struct VoteOnResourceResponse {
+    pub score: i32,
+    pub vote: i16
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = resource_comments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ResourceComment {
+    pub comment_id: Uuid,
+    pub resource_id: Uuid,
+    pub parent_id: Option<Uuid>, /* None for top level comments */
+    pub author_id: Uuid,
+    pub body: String,
+    pub created_at: chrono::DateTime<Utc>,
+    pub edited_at: Option<chrono::DateTime<Utc>>,
+    pub is_deleted: bool,
+    pub is_removed: bool
+}
+
+/// A comment as shown to users, deleted and removed comments stay in their thread without their body and author
+#[derive(Serialize)]
+pub struct ResourceCommentResponse {
+    pub comment_id: Uuid,
+    pub resource_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub author_id: Option<Uuid>,
+    pub body: Option<String>,
+    pub created_at: chrono::DateTime<Utc>,
+    pub edited_at: Option<chrono::DateTime<Utc>>,
+    pub is_deleted: bool,
+    pub is_removed: bool
+}
+
+impl From<ResourceComment> for ResourceCommentResponse {
+    fn from(comment: ResourceComment) -> Self {
+        let visible = !comment.is_deleted && !comment.is_removed;
+        ResourceCommentResponse {
+            comment_id: comment.comment_id,
+            resource_id: comment.resource_id,
+            parent_id: comment.parent_id,
+            author_id: visible.then_some(comment.author_id),
+            body: visible.then_some(comment.body),
+            created_at: comment.created_at,
+            edited_at: comment.edited_at,
+            is_deleted: comment.is_deleted,
+            is_removed: comment.is_removed
+        }
+    }
+}
+
+/// Body of `POST /v1/course_resource/:resource_id/comments`
+#[derive(Deserialize)]
+pub struct PostResourceComment {
+    pub body: String,
+    pub parent_id: Option<Uuid>
+}
+
+/// Body of `PATCH /v1/course_resource/:resource_id/comments/:comment_id`
+#[derive(Deserialize)]
+pub struct EditResourceComment {
+    pub body: String
+}
+
+#[derive(Deserialize)]
+pub struct GetResourceCommentsQuery {
+    pub page: Option<i64>
+}
+
+/// Comments are oldest first and flat, replies are threaded under their parent using `parent_id`
+#[derive(Serialize)]
+pub struct GetResourceCommentsResponse {
+    pub comments: Vec<ResourceCommentResponse>,
+    pub total_comments: i64, /* Visible comments, the same as comment_count on the resource */
+    pub total_entries: i64 /* Entries to page through, including the placeholders of deleted and removed comments */
+}
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = faculties)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Faculty {
+    pub faculty_id: Faculties,
+    pub name_en: String,
+    pub name_ar: String
+}
+
+#[derive(Serialize)]
+pub struct FacultyResponse {
+    #[serde(flatten)]
+    pub faculty: Faculty,
+    pub course_count: i64
+}
+
+/// Query of `GET /v1/search`
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub faculty: Option<Faculties>,
+    pub resource_type: Option<ResourceType>,
+    pub page: Option<i64>
+}
+
+/// One page of the results of a single type, and how many there are in total
+#[derive(Serialize)]
+pub struct SearchResultGroup<T> {
+    pub results: Vec<T>,
+    pub total: i64
+}
+
+#[derive(Serialize)]
+pub struct SearchResourceResult {
+    #[serde(flatten)]
+    pub resource: CourseResource,
+    pub course_name: String
+}
+
+#[derive(Serialize)]
+pub struct SearchFileResult {
+    #[serde(flatten)]
+    pub file: CourseResourceFile,
+    pub resource_title: String,
+    pub resource_type: ResourceType,
+    pub course_id: String,
+    pub course_name: String
+}
+
+/// The same fields as the links of a course, without the moderation and health check ones
+#[derive(Serialize)]
+pub struct SearchLinkResult {
+    #[serde(flatten)]
+    pub link: CourseDetailsLinkResponse,
+    pub course_id: String,
+    pub course_name: String
+}
+
+#[derive(Serialize)]
+pub struct SearchResponse {
+    pub resources: SearchResultGroup<SearchResourceResult>,
+    pub files: SearchResultGroup<SearchFileResult>,
+    pub links: SearchResultGroup<SearchLinkResult>
+}
@@ -0,0 +1,129 @@
+//! Users rating courses on difficulty, workload and usefulness, from 1 to 5.
+//! Each user has one rating per course, rating it again replaces it.
+use std::collections::HashMap;
+
+use axum::{extract::{Path, Query}, http::StatusCode, response::IntoResponse, Json};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::accounts::AuthenticatedUser;
+use crate::connection::DbConn;
+use crate::course_retreival::sanitize_page_input;
+use crate::models::{error_response, CourseRating, CourseRatingSummary, GetCourseRatingsQuery, GetCourseRatingsResponse, RateCourse};
+use crate::schema::{course_ratings, courses};
+
+const RATINGS_PER_PAGE: i64 = 20;
+const MAX_REVIEW_LENGTH: usize = 2000;
+
+type RatingSums = (String, i64, Option<i64>, Option<i64>, Option<i64>);
+
+fn rating_summary_from_sums((_, count, difficulty, workload, usefulness): RatingSums) -> CourseRatingSummary {
+    let average = |total: Option<i64>| total.filter(|_| count > 0).map(|total| total as f64 / count as f64);
+    CourseRatingSummary {
+        rating_count: count,
+        average_difficulty: average(difficulty),
+        average_workload: average(workload),
+        average_usefulness: average(usefulness)
+    }
+}
+
+/// Rating summaries of the given courses, courses nobody rated are left out
+pub fn get_course_rating_summaries_from_db(conn: &mut PgConnection, course_ids: &[String]) -> Result<HashMap<String, CourseRatingSummary>, diesel::result::Error> {
+    let sums = course_ratings::table
+        .filter(course_ratings::course_id.eq_any(course_ids))
+        .group_by(course_ratings::course_id)
+        .select((course_ratings::course_id, count_star(), diesel::dsl::sum(course_ratings::difficulty), diesel::dsl::sum(course_ratings::workload), diesel::dsl::sum(course_ratings::usefulness)))
+        .load::<RatingSums>(conn)?;
+
+    Ok(sums.into_iter().map(|sums| (sums.0.clone(), rating_summary_from_sums(sums))).collect())
+}
+
+pub fn get_course_rating_summary_from_db(conn: &mut PgConnection, course_id: &str) -> Result<CourseRatingSummary, diesel::result::Error> {
+    let mut summaries = get_course_rating_summaries_from_db(conn, &[course_id.to_string()])?;
+    Ok(summaries.remove(course_id).unwrap_or_default())
+}
+
+fn validate_rating(payload: RateCourse) -> Result<RateCourse, String> {
+    for (name, score) in [("Difficulty", payload.difficulty), ("Workload", payload.workload), ("Usefulness", payload.usefulness)] {
+        if !(1..=5).contains(&score) {
+            return Err(format!("{} must be between 1 and 5", name));
+        }
+    }
+
+    let review = payload.review.map(|review| review.trim().to_string()).filter(|review| !review.is_empty());
+    if review.as_ref().is_some_and(|review| review.chars().count() > MAX_REVIEW_LENGTH) {
+        return Err(format!("Review can't be longer than {} characters", MAX_REVIEW_LENGTH));
+    }
+
+    Ok(RateCourse { review, ..payload })
+}
+
+pub async fn rate_course(AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Path(course_id): Path<String>, Json(payload): Json<RateCourse>) -> Result<impl IntoResponse, StatusCode> {
+    let course_id = course_id.to_uppercase();
+    let payload = match validate_rating(payload) {
+        Ok(payload) => payload,
+        Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, e))
+    };
+
+    match courses::table.find(&course_id).select(count_star()).get_result::<i64>(&mut conn) {
+        Ok(0) => return Ok(error_response(StatusCode::NOT_FOUND, format!("Course with id {} not found", course_id))),
+        Ok(_) => {},
+        Err(e) => return Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+
+    let now = chrono::Utc::now();
+    let rating = CourseRating {
+        rating_id: Uuid::new_v4(),
+        course_id,
+        user_id: user.user_id,
+        difficulty: payload.difficulty,
+        workload: payload.workload,
+        usefulness: payload.usefulness,
+        review: payload.review,
+        created_at: now,
+        updated_at: now
+    };
+
+    let saved = diesel::insert_into(course_ratings::table)
+        .values(&rating)
+        .on_conflict((course_ratings::course_id, course_ratings::user_id))
+        .do_update()
+        .set((
+            course_ratings::difficulty.eq(rating.difficulty),
+            course_ratings::workload.eq(rating.workload),
+            course_ratings::usefulness.eq(rating.usefulness),
+            course_ratings::review.eq(&rating.review),
+            course_ratings::updated_at.eq(now)
+        ))
+        .returning(CourseRating::as_returning())
+        .get_result(&mut conn);
+
+    match saved {
+        Ok(rating) => Ok(Json(rating).into_response()),
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
+
+/// Newest ratings first, `summary.rating_count` is the total for pagination
+pub async fn get_course_ratings(DbConn(mut conn): DbConn, Path(course_id): Path<String>, Query(query): Query<GetCourseRatingsQuery>) -> Result<impl IntoResponse, StatusCode> {
+    let course_id = course_id.to_uppercase();
+    match courses::table.find(&course_id).select(count_star()).get_result::<i64>(&mut conn) {
+        Ok(0) => return Ok(error_response(StatusCode::NOT_FOUND, format!("Course with id {} not found", course_id))),
+        Ok(_) => {},
+        Err(e) => return Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+
+    let ratings = course_ratings::table
+        .filter(course_ratings::course_id.eq(&course_id))
+        .order(course_ratings::updated_at.desc())
+        .limit(RATINGS_PER_PAGE)
+        .offset((sanitize_page_input(query.page) - 1) * RATINGS_PER_PAGE)
+        .select(CourseRating::as_select())
+        .load(&mut conn);
+
+    match (ratings, get_course_rating_summary_from_db(&mut conn, &course_id)) {
+        (Ok(ratings), Ok(summary)) => Ok(Json(GetCourseRatingsResponse { ratings, summary }).into_response()),
+        (Err(e), _) | (_, Err(e)) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
@@ -0,0 +1,128 @@
+//! Users flagging resources, files and links that are wrong or inappropriate.
+//! Once an item collects `REPORT_HIDE_THRESHOLD` pending reports it's hidden until a moderator reviews them.
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
+use uuid::Uuid;
+
+use crate::accounts::AuthenticatedUser;
+use crate::connection::DbConn;
+use crate::models::{error_response, CreateReport, CreateReportResponse, Report, ReportTargetType};
+use crate::schema::{course_resource_files, course_resource_links, course_resources, reports};
+use crate::state::AppState;
+
+const MAX_REPORT_DETAILS_LENGTH: usize = 1000;
+
+/// How many pending reports hide an item, configured with `REPORT_HIDE_THRESHOLD` (default 3)
+pub fn report_hide_threshold_from_env() -> i64 {
+    dotenvy::var("REPORT_HIDE_THRESHOLD").ok().and_then(|v| v.parse().ok()).filter(|threshold| *threshold > 0).unwrap_or(3)
+}
+
+/// Locks the reported item's row until the transaction ends, so reports on it are counted and acted on one at a time.
+/// Returns whether the item exists
+pub fn lock_report_target(conn: &mut PgConnection, target_type: ReportTargetType, target_id: Uuid) -> Result<bool, diesel::result::Error> {
+    let locked = match target_type {
+        ReportTargetType::Resource => course_resources::table.find(target_id).select(course_resources::resource_id).for_update().first::<Uuid>(conn).optional()?,
+        ReportTargetType::File => course_resource_files::table.find(target_id).select(course_resource_files::file_id).for_update().first::<Uuid>(conn).optional()?,
+        ReportTargetType::Link => course_resource_links::table.find(target_id).select(course_resource_links::link_id).for_update().first::<Uuid>(conn).optional()?
+    };
+
+    Ok(locked.is_some())
+}
+
+/// Hides or shows the reported item, returning how many rows actually changed
+pub fn set_report_target_hidden(conn: &mut PgConnection, target_type: ReportTargetType, target_id: Uuid, hidden: bool) -> Result<usize, diesel::result::Error> {
+    match target_type {
+        ReportTargetType::Resource => diesel::update(course_resources::table.find(target_id).filter(course_resources::is_hidden.ne(hidden)))
+            .set(course_resources::is_hidden.eq(hidden))
+            .execute(conn),
+        ReportTargetType::File => diesel::update(course_resource_files::table.find(target_id).filter(course_resource_files::is_hidden.ne(hidden)))
+            .set(course_resource_files::is_hidden.eq(hidden))
+            .execute(conn),
+        ReportTargetType::Link => diesel::update(course_resource_links::table.find(target_id).filter(course_resource_links::is_hidden.ne(hidden)))
+            .set(course_resource_links::is_hidden.eq(hidden))
+            .execute(conn)
+    }
+}
+
+/// Forgets that the pending reports hid the item, once a moderator sets its visibility themselves
+pub fn clear_report_hide(conn: &mut PgConnection, target_type: ReportTargetType, target_id: Uuid) -> Result<usize, diesel::result::Error> {
+    diesel::update(reports::table)
+        .filter(reports::target_type.eq(target_type.as_str()))
+        .filter(reports::target_id.eq(target_id))
+        .filter(reports::status.eq(Report::PENDING))
+        .filter(reports::hid_target.eq(true))
+        .set(reports::hid_target.eq(false))
+        .execute(conn)
+}
+
+/// Parses the target type stored in a report row
+pub fn report_target_type(report: &Report) -> Option<ReportTargetType> {
+    match report.target_type.as_str() {
+        "resource" => Some(ReportTargetType::Resource),
+        "file" => Some(ReportTargetType::File),
+        "link" => Some(ReportTargetType::Link),
+        _ => None
+    }
+}
+
+pub async fn create_report(State(state): State<AppState>, AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Json(payload): Json<CreateReport>) -> Result<impl IntoResponse, StatusCode> {
+    let details = payload.details.map(|details| details.trim().to_string()).filter(|details| !details.is_empty());
+    if details.as_ref().is_some_and(|details| details.chars().count() > MAX_REPORT_DETAILS_LENGTH) {
+        return Ok(error_response(StatusCode::BAD_REQUEST, format!("Details can't be longer than {} characters", MAX_REPORT_DETAILS_LENGTH)));
+    }
+
+    let new_report = Report {
+        report_id: Uuid::new_v4(),
+        reporter_id: user.user_id,
+        target_type: payload.target_type.as_str().to_string(),
+        target_id: payload.target_id,
+        reason: payload.reason.as_str().to_string(),
+        details,
+        status: Report::PENDING.to_string(),
+        created_at: chrono::Utc::now(),
+        hid_target: false
+    };
+
+    let created = conn.transaction(|conn| {
+        if !lock_report_target(conn, payload.target_type, payload.target_id)? {
+            return Ok(None);
+        }
+
+        let report = diesel::insert_into(reports::table)
+            .values(&new_report)
+            .returning(Report::as_returning())
+            .get_result(conn)?;
+
+        let pending_reports = reports::table
+            .filter(reports::target_type.eq(&report.target_type))
+            .filter(reports::target_id.eq(report.target_id))
+            .filter(reports::status.eq(Report::PENDING))
+            .select(count_star())
+            .get_result::<i64>(conn)?;
+
+        let target_hidden = pending_reports >= state.report_hide_threshold
+            && set_report_target_hidden(conn, payload.target_type, payload.target_id, true)? > 0;
+
+        // Remembered so dismissing the reports only shows items that the reports hid
+        let report = match target_hidden {
+            true => diesel::update(reports::table.find(report.report_id))
+                .set(reports::hid_target.eq(true))
+                .returning(Report::as_returning())
+                .get_result(conn)?,
+            false => report
+        };
+
+        Ok::<_, diesel::result::Error>(Some(CreateReportResponse { report, target_hidden }))
+    });
+
+    match created {
+        Ok(Some(response)) => Ok((StatusCode::CREATED, Json(response)).into_response()),
+        Ok(None) => Ok(error_response(StatusCode::NOT_FOUND, format!("No {} with id {}", payload.target_type.as_str(), payload.target_id))),
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Ok(error_response(StatusCode::CONFLICT, format!("You already reported this {}", payload.target_type.as_str())))
+        },
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
@@ -0,0 +1,58 @@
+//! What kind of resource a course resource is, stored and sent as an int.
+//! To add a type, add a variant with the next value, add it to `ResourceType::ALL` with its name,
+//! and widen the `course_resources_resource_type_check` constraint in a migration.
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::SmallInt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = SmallInt)]
+#[serde(try_from = "i16", into = "i16")]
+#[repr(i16)]
+pub enum ResourceType {
+    Notes = 0,
+    Exams = 1
+}
+
+impl ResourceType {
+    pub const ALL: [(ResourceType, &'static str); 2] = [
+        (ResourceType::Notes, "Notes"),
+        (ResourceType::Exams, "Exams")
+    ];
+}
+
+impl From<ResourceType> for i16 {
+    fn from(resource_type: ResourceType) -> Self {
+        resource_type as i16
+    }
+}
+
+impl TryFrom<i16> for ResourceType {
+    type Error = String;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        if let Some((resource_type, _)) = ResourceType::ALL.iter().find(|(resource_type, _)| i16::from(*resource_type) == value) {
+            return Ok(*resource_type);
+        }
+
+        let valid_types: Vec<String> = ResourceType::ALL.iter()
+            .map(|(resource_type, name)| format!("{} for {}", i16::from(*resource_type), name))
+            .collect();
+        Err(format!("Invalid resource type {} (Must be one of {})", value, valid_types.join(", ")))
+    }
+}
+
+impl ToSql<SmallInt, Pg> for ResourceType {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <i16 as ToSql<SmallInt, Pg>>::to_sql(&i16::from(*self), &mut out.reborrow())
+    }
+}
+
+impl FromSql<SmallInt, Pg> for ResourceType {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        Ok(ResourceType::try_from(<i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)?)?)
+    }
+}
@@ -0,0 +1,319 @@
+//! Uploading the files of a course resource straight from the multipart request body.
+//! Each file is piped chunk by chunk to the storage backend while the request is still being read,
+//! and the uploads of separate files overlap, up to a limit per request.
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+use crate::models::CourseResourceFile;
+use crate::storage::{file_content_type, ByteStream, StorageBackend, StorageError};
+
+// How many chunks of a file can be buffered while waiting on the storage backend
+const UPLOAD_CHANNEL_CAPACITY: usize = 8;
+
+// Where a file of a resource lives in the storage backend.
+// The file id keeps uploads that pick the same name at the same time from overwriting each other
+pub fn course_resource_file_key(course_id: &str, resource_id: Uuid, file_id: Uuid, file_name: &str) -> String {
+    format!("course_resources/{}/{}/{}/{}", course_id, resource_id, file_id, file_name)
+}
+
+// Files uploaded before keys included the file id are still stored without it
+fn stored_course_resource_file_key(storage: &dyn StorageBackend, course_id: &str, file: &CourseResourceFile) -> String {
+    let key = course_resource_file_key(course_id, file.resource_id, file.file_id, &file.file_name);
+    if storage.url(&key) == file.file_url {
+        return key;
+    }
+    format!("course_resources/{}/{}/{}", course_id, file.resource_id, file.file_name)
+}
+
+fn sanitize_file_name_to_upload(file_name: &str) -> String {
+    return file_name.replace(" ", "_")
+    .replace("#", "_")
+    .replace("'", "_")
+    .replace("\"", "_")
+    .replace(":", "_")
+    .replace(";", "_")
+    .replace("|", "_")
+    .replace("/", "_")
+    .replace("\\", "_");
+}
+
+/// Removes the objects of the given files from storage.
+/// Best-effort, failures are only logged since this runs as cleanup after something else already went wrong
+/// (or after the rows are gone, where a leftover object is harmless)
+pub async fn delete_resource_files_from_storage(storage: &dyn StorageBackend, course_id: &str, files: &[CourseResourceFile]) {
+    for file in files {
+        let key = stored_course_resource_file_key(storage, course_id, file);
+        if let Err(e) = storage.delete(&key).await {
+            eprintln!("Failed to delete object {}: {}", key, e);
+        }
+    }
+}
+
+/// The uploads for the files of a single resource
+pub struct ResourceFileUploads {
+    storage: Arc<dyn StorageBackend>,
+    course_id: String,
+    resource_id: Uuid,
+    permits: Arc<Semaphore>,
+    tasks: JoinSet<Result<CourseResourceFile, StorageError>>,
+    file_names: HashSet<String>,
+    existing_file_names: HashSet<String>,
+}
+
+impl ResourceFileUploads {
+    pub fn new(storage: Arc<dyn StorageBackend>, course_id: String, resource_id: Uuid, max_concurrent_uploads: usize) -> Self {
+        ResourceFileUploads {
+            storage,
+            course_id,
+            resource_id,
+            permits: Arc::new(Semaphore::new(max_concurrent_uploads.max(1))),
+            tasks: JoinSet::new(),
+            file_names: HashSet::new(),
+            existing_file_names: HashSet::new(),
+        }
+    }
+
+    /// For adding files to a resource that already has some, so the new ones don't overwrite them
+    pub fn with_existing_file_names(mut self, file_names: impl IntoIterator<Item = String>) -> Self {
+        self.existing_file_names.extend(file_names);
+        self
+    }
+
+    pub fn resource_id(&self) -> Uuid {
+        self.resource_id
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.file_names.is_empty()
+    }
+
+    // Two files with the same name would otherwise end up as the same object
+    fn unique_file_name(&mut self, file_name: String) -> String {
+        if !self.existing_file_names.contains(&file_name) && self.file_names.insert(file_name.clone()) {
+            return file_name;
+        }
+
+        let (stem, extension) = match file_name.rsplit_once('.') {
+            Some((stem, extension)) => (stem.to_string(), format!(".{}", extension)),
+            None => (file_name.clone(), String::new())
+        };
+
+        (1..)
+            .map(|n| format!("{}_{}{}", stem, n, extension))
+            .find(|candidate| !self.existing_file_names.contains(candidate) && self.file_names.insert(candidate.clone()))
+            .unwrap()
+    }
+
+    /// Starts uploading a file and feeds it everything from `chunks`.
+    /// Returns once the whole file has been read, which can be before the upload is done.
+    /// Errors only when reading `chunks` fails, storage errors are reported by `finish`
+    pub async fn upload<E: std::fmt::Display>(&mut self, file_name: &str, chunks: impl Stream<Item = Result<Bytes, E>>) -> Result<(), std::io::Error> {
+        let file_name = self.unique_file_name(sanitize_file_name_to_upload(file_name));
+        let file_id = Uuid::new_v4();
+        let key = course_resource_file_key(&self.course_id, self.resource_id, file_id, &file_name);
+
+        // Wait for one of the in-flight uploads to finish if we're at the limit
+        let permit = self.permits.clone().acquire_owned().await.expect("upload semaphore is never closed");
+
+        let (sender, receiver) = mpsc::channel::<Result<Bytes, std::io::Error>>(UPLOAD_CHANNEL_CAPACITY);
+        let body: ByteStream = Box::pin(futures_util::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|chunk| (chunk, receiver))
+        }));
+
+        let storage = self.storage.clone();
+        let resource_file = CourseResourceFile {
+            file_id,
+            file_name: file_name.clone(),
+            file_url: storage.url(&key),
+            resource_id: self.resource_id,
+            is_hidden: false
+        };
+
+        self.tasks.spawn(async move {
+            let _permit = permit;
+            storage.put(&key, file_content_type(&key), body).await?;
+            Ok(resource_file)
+        });
+
+        let mut chunks = std::pin::pin!(chunks);
+        while let Some(chunk) = chunks.next().await {
+            match chunk {
+                Ok(chunk) => {
+                    // The upload already failed, the error will come out of `finish`
+                    if sender.send(Ok(chunk)).await.is_err() {
+                        break;
+                    }
+                },
+                Err(e) => {
+                    let message = format!("Failed to read file {}: {}", file_name, e);
+                    _ = sender.send(Err(std::io::Error::other(message.clone()))).await;
+                    return Err(std::io::Error::other(message));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Waits for every upload to complete.
+    /// If any of them failed, the ones that succeeded are deleted again and the first error is returned
+    pub async fn finish(mut self) -> Result<Vec<CourseResourceFile>, StorageError> {
+        let mut uploaded: Vec<CourseResourceFile> = Vec::new();
+        let mut failure: Option<StorageError> = None;
+        while let Some(joined) = self.tasks.join_next().await {
+            match joined {
+                Ok(Ok(file)) => uploaded.push(file),
+                Ok(Err(e)) => { failure.get_or_insert(e); },
+                Err(e) => { failure.get_or_insert(StorageError::Request(e.to_string())); }
+            }
+        }
+
+        if let Some(e) = failure {
+            delete_resource_files_from_storage(self.storage.as_ref(), &self.course_id, &uploaded).await;
+            return Err(e);
+        }
+
+        Ok(uploaded)
+    }
+
+    /// Gives up on the resource, removing anything that was already uploaded
+    pub async fn abort(self) {
+        let storage = self.storage.clone();
+        let course_id = self.course_id.clone();
+        if let Ok(uploaded) = self.finish().await {
+            delete_resource_files_from_storage(storage.as_ref(), &course_id, &uploaded).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use futures_util::stream;
+
+    use super::*;
+    use crate::storage::LocalStorage;
+    use crate::test_support::TempDir;
+
+    /// Local storage where uploading one particular file always fails
+    struct FailingStorage {
+        inner: LocalStorage,
+        failing_file_name: &'static str,
+    }
+
+    #[async_trait]
+    impl StorageBackend for FailingStorage {
+        async fn put(&self, key: &str, content_type: &str, mut data: ByteStream) -> Result<(), StorageError> {
+            if key.ends_with(self.failing_file_name) {
+                // Read a bit first so the failure happens partway through the upload
+                _ = data.next().await;
+                return Err(StorageError::Request(format!("Uploading {} failed", key)));
+            }
+            self.inner.put(key, content_type, data).await
+        }
+
+        async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+            self.inner.get(key).await
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), StorageError> {
+            self.inner.delete(key).await
+        }
+
+        fn url(&self, key: &str) -> String {
+            self.inner.url(key)
+        }
+    }
+
+    fn chunks(parts: &[&'static str]) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+        stream::iter(parts.iter().map(|part| Ok(Bytes::from_static(part.as_bytes()))).collect::<Vec<_>>())
+    }
+
+    #[tokio::test]
+    async fn uploads_every_file() {
+        let dir = TempDir::new();
+        let resource_id = Uuid::new_v4();
+        let mut uploads = ResourceFileUploads::new(Arc::new(dir.storage()), "CS116".to_string(), resource_id, 2);
+        for file_name in ["midterm.pdf", "final.pdf", "midterm.pdf"] {
+            uploads.upload(file_name, chunks(&["hello ", "world"])).await.unwrap();
+        }
+
+        let files = uploads.finish().await.unwrap();
+        let mut file_names: Vec<&str> = files.iter().map(|file| file.file_name.as_str()).collect();
+        file_names.sort();
+        assert_eq!(file_names, ["final.pdf", "midterm.pdf", "midterm_1.pdf"]);
+        for file in &files {
+            let content = dir.storage().get(&course_resource_file_key("CS116", resource_id, file.file_id, &file.file_name)).await.unwrap();
+            assert_eq!(content, b"hello world");
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_uploads_of_the_same_name_keep_both_files() {
+        let dir = TempDir::new();
+        let resource_id = Uuid::new_v4();
+        let mut first = ResourceFileUploads::new(Arc::new(dir.storage()), "CS116".to_string(), resource_id, 2);
+        let mut second = ResourceFileUploads::new(Arc::new(dir.storage()), "CS116".to_string(), resource_id, 2);
+        first.upload("notes.pdf", chunks(&["first"])).await.unwrap();
+        second.upload("notes.pdf", chunks(&["second"])).await.unwrap();
+
+        let first = first.finish().await.unwrap().remove(0);
+        let second = second.finish().await.unwrap().remove(0);
+        assert_ne!(first.file_url, second.file_url);
+        assert_eq!(dir.storage().get(&course_resource_file_key("CS116", resource_id, first.file_id, &first.file_name)).await.unwrap(), b"first");
+        assert_eq!(dir.storage().get(&course_resource_file_key("CS116", resource_id, second.file_id, &second.file_name)).await.unwrap(), b"second");
+    }
+
+    #[tokio::test]
+    async fn deletes_files_stored_before_keys_had_the_file_id() {
+        let dir = TempDir::new();
+        let storage = dir.storage();
+        let key = format!("course_resources/CS116/{}/old.pdf", Uuid::new_v4());
+        storage.put(&key, "application/pdf", Box::pin(chunks(&["old"]))).await.unwrap();
+        let file = CourseResourceFile {
+            file_id: Uuid::new_v4(),
+            file_name: "old.pdf".to_string(),
+            file_url: storage.url(&key),
+            resource_id: key.split('/').nth(2).unwrap().parse().unwrap(),
+            is_hidden: false
+        };
+
+        delete_resource_files_from_storage(&storage, "CS116", &[file]).await;
+        assert!(dir.files().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_upload_deletes_the_files_already_uploaded() {
+        let dir = TempDir::new();
+        let resource_id = Uuid::new_v4();
+        let storage = Arc::new(FailingStorage { inner: dir.storage(), failing_file_name: "b.pdf" });
+        let mut uploads = ResourceFileUploads::new(storage, "CS116".to_string(), resource_id, 2);
+        for file_name in ["a.pdf", "b.pdf", "c.pdf"] {
+            uploads.upload(file_name, chunks(&["hello ", "world"])).await.unwrap();
+        }
+
+        assert!(matches!(uploads.finish().await, Err(StorageError::Request(_))));
+        assert_eq!(dir.files(), Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn read_error_removes_the_partial_file() {
+        let dir = TempDir::new();
+        let resource_id = Uuid::new_v4();
+        let mut uploads = ResourceFileUploads::new(Arc::new(dir.storage()), "CS116".to_string(), resource_id, 2);
+        uploads.upload("a.pdf", chunks(&["hello ", "world"])).await.unwrap();
+
+        let broken = stream::iter(vec![Ok(Bytes::from_static(b"partial")), Err("connection reset")]);
+        let error = uploads.upload("b.pdf", broken).await.unwrap_err();
+        assert!(error.to_string().contains("connection reset"));
+
+        uploads.abort().await;
+        assert_eq!(dir.files(), Vec::<String>::new());
+    }
+}
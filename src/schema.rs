@@ -1,11 +1,38 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    admin_audit_log (audit_id) {
+        audit_id -> Uuid,
+        admin_id -> Uuid,
+        action -> Varchar,
+        target_type -> Varchar,
+        target_id -> Varchar,
+        details -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    course_ratings (rating_id) {
+        rating_id -> Uuid,
+        course_id -> Varchar,
+        user_id -> Uuid,
+        difficulty -> Int2,
+        workload -> Int2,
+        usefulness -> Int2,
+        review -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     course_resource_files (file_id) {
         file_id -> Uuid,
         file_name -> Varchar,
         file_url -> Varchar,
         resource_id -> Uuid,
+        is_hidden -> Bool,
     }
 }
 
@@ -15,6 +42,13 @@ diesel::table! {
         link_title -> Varchar,
         link_url -> Varchar,
         course_id -> Varchar,
+        uploader_id -> Nullable<Uuid>,
+        is_hidden -> Bool,
+        date_added -> Timestamptz,
+        link_kind -> Varchar,
+        last_checked -> Nullable<Timestamptz>,
+        last_status -> Nullable<Int2>,
+        consecutive_failures -> Int4,
     }
 }
 
@@ -29,6 +63,9 @@ diesel::table! {
         semester -> Varchar,
         academic_year -> Int4,
         issolved -> Bool,
+        uploader_id -> Nullable<Uuid>,
+        is_hidden -> Bool,
+        score -> Int4,
     }
 }
 
@@ -37,16 +74,102 @@ diesel::table! {
         course_id -> Varchar,
         course_name -> Varchar,
         course_faculty -> Int2,
+        is_active -> Bool,
+        edited_by_admin -> Bool,
+    }
+}
+
+diesel::table! {
+    email_verification_codes (email) {
+        email -> Varchar,
+        code_hash -> Varchar,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        attempts -> Int4,
+    }
+}
+
+diesel::table! {
+    faculties (faculty_id) {
+        faculty_id -> Int2,
+        name_en -> Varchar,
+        name_ar -> Varchar,
+    }
+}
+
+diesel::table! {
+    reports (report_id) {
+        report_id -> Uuid,
+        reporter_id -> Uuid,
+        target_type -> Varchar,
+        target_id -> Uuid,
+        reason -> Varchar,
+        details -> Nullable<Varchar>,
+        status -> Varchar,
+        created_at -> Timestamptz,
+        hid_target -> Bool,
+    }
+}
+
+diesel::table! {
+    resource_comments (comment_id) {
+        comment_id -> Uuid,
+        resource_id -> Uuid,
+        parent_id -> Nullable<Uuid>,
+        author_id -> Uuid,
+        body -> Varchar,
+        created_at -> Timestamptz,
+        edited_at -> Nullable<Timestamptz>,
+        is_deleted -> Bool,
+        is_removed -> Bool,
+    }
+}
+
+diesel::table! {
+    resource_votes (resource_id, user_id) {
+        resource_id -> Uuid,
+        user_id -> Uuid,
+        vote -> Int2,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    users (user_id) {
+        user_id -> Uuid,
+        email -> Varchar,
+        created_at -> Timestamptz,
+        role -> Varchar,
+        is_banned -> Bool,
     }
 }
 
+diesel::joinable!(admin_audit_log -> users (admin_id));
+diesel::joinable!(course_ratings -> courses (course_id));
+diesel::joinable!(course_ratings -> users (user_id));
 diesel::joinable!(course_resource_files -> course_resources (resource_id));
 diesel::joinable!(course_resource_links -> courses (course_id));
+diesel::joinable!(course_resource_links -> users (uploader_id));
 diesel::joinable!(course_resources -> courses (course_id));
+diesel::joinable!(course_resources -> users (uploader_id));
+diesel::joinable!(courses -> faculties (course_faculty));
+diesel::joinable!(reports -> users (reporter_id));
+diesel::joinable!(resource_comments -> course_resources (resource_id));
+diesel::joinable!(resource_comments -> users (author_id));
+diesel::joinable!(resource_votes -> course_resources (resource_id));
+diesel::joinable!(resource_votes -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    admin_audit_log,
+    course_ratings,
     course_resource_files,
     course_resource_links,
     course_resources,
     courses,
+    email_verification_codes,
+    faculties,
+    reports,
+    resource_comments,
+    resource_votes,
+    users,
 );
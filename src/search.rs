@@ -0,0 +1,141 @@
+//! Site wide search over resources, their files and links, at `GET /v1/search`.
+//! Every word of the query has to match the start of a word in the item, so "final solved" finds solved finals.
+//! Resources are matched on their title, subtitle, semester, year and whether they're solved, files on their name
+//! and links on their title. Hidden items and the files of hidden resources never show up.
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use diesel::dsl::{count_star, sql, InnerJoin, IntoBoxed};
+use diesel::expression::BoxableExpression;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Double, Text};
+
+use crate::connection::DbConn;
+use crate::course_retreival::sanitize_page_input;
+use crate::course_search::prefix_tsquery;
+use crate::models::{error_response, CourseResource, CourseResourceFile, CourseResourceLink, SearchFileResult, SearchLinkResult, SearchQuery, SearchResourceResult, SearchResponse, SearchResultGroup};
+use crate::schema::{course_resource_files, course_resource_links, course_resources, courses};
+
+const SEARCH_RESULTS_PER_PAGE: i64 = 10;
+
+const RESOURCE_DOCUMENT: &str = "to_tsvector('simple', course_resources.title || ' ' || coalesce(course_resources.subtitle, '') || ' ' \
+    || course_resources.semester || ' ' || course_resources.academic_year || CASE WHEN course_resources.issolved THEN ' solved' ELSE '' END)";
+// File names are sanitized with underscores instead of spaces, which the parser would keep as part of the word
+const FILE_DOCUMENT: &str = "to_tsvector('simple', regexp_replace(course_resource_files.file_name, '[^[:alnum:]]+', ' ', 'g'))";
+const LINK_DOCUMENT: &str = "to_tsvector('simple', course_resource_links.link_title)";
+
+type ResourceSearchQuery<'a> = IntoBoxed<'a, InnerJoin<course_resources::table, courses::table>, Pg>;
+type FileSearchQuery<'a> = IntoBoxed<'a, InnerJoin<course_resource_files::table, InnerJoin<course_resources::table, courses::table>>, Pg>;
+type LinkSearchQuery<'a> = IntoBoxed<'a, InnerJoin<course_resource_links::table, courses::table>, Pg>;
+
+fn matching_resources_query<'a>(tsquery: &str, query: &SearchQuery) -> ResourceSearchQuery<'a> {
+    let mut resources = course_resources::table
+        .inner_join(courses::table)
+        .filter(course_resources::is_hidden.eq(false))
+        .filter(sql::<Bool>(&format!("{} @@ to_tsquery('simple', ", RESOURCE_DOCUMENT)).bind::<Text, _>(tsquery.to_string()).sql(")"))
+        .into_boxed();
+
+    if let Some(faculty) = query.faculty {
+        resources = resources.filter(courses::course_faculty.eq(faculty));
+    }
+
+    if let Some(resource_type) = query.resource_type {
+        resources = resources.filter(course_resources::resource_type.eq(resource_type));
+    }
+
+    resources
+}
+
+fn matching_files_query<'a>(tsquery: &str, query: &SearchQuery) -> FileSearchQuery<'a> {
+    let mut files = course_resource_files::table
+        .inner_join(course_resources::table.inner_join(courses::table))
+        .filter(course_resource_files::is_hidden.eq(false).and(course_resources::is_hidden.eq(false)))
+        .filter(sql::<Bool>(&format!("{} @@ to_tsquery('simple', ", FILE_DOCUMENT)).bind::<Text, _>(tsquery.to_string()).sql(")"))
+        .into_boxed();
+
+    if let Some(faculty) = query.faculty {
+        files = files.filter(courses::course_faculty.eq(faculty));
+    }
+
+    if let Some(resource_type) = query.resource_type {
+        files = files.filter(course_resources::resource_type.eq(resource_type));
+    }
+
+    files
+}
+
+fn matching_links_query<'a>(tsquery: &str, query: &SearchQuery) -> LinkSearchQuery<'a> {
+    let mut links = course_resource_links::table
+        .inner_join(courses::table)
+        .filter(course_resource_links::is_hidden.eq(false))
+        .filter(sql::<Bool>(&format!("{} @@ to_tsquery('simple', ", LINK_DOCUMENT)).bind::<Text, _>(tsquery.to_string()).sql(")"))
+        .into_boxed();
+
+    if let Some(faculty) = query.faculty {
+        links = links.filter(courses::course_faculty.eq(faculty));
+    }
+
+    links
+}
+
+fn rank<QS>(document: &str, tsquery: &str) -> Box<dyn BoxableExpression<QS, Pg, SqlType = Double>> {
+    Box::new(sql::<Double>(&format!("ts_rank({}, to_tsquery('simple', ", document)).bind::<Text, _>(tsquery.to_string()).sql("))::float8"))
+}
+
+fn search_in_db(conn: &mut PgConnection, tsquery: &str, query: &SearchQuery) -> Result<SearchResponse, diesel::result::Error> {
+    let offset = (sanitize_page_input(query.page) - 1) * SEARCH_RESULTS_PER_PAGE;
+
+    let resources = matching_resources_query(tsquery, query)
+        .order((rank(RESOURCE_DOCUMENT, tsquery).desc(), course_resources::dateuploaded.desc()))
+        .limit(SEARCH_RESULTS_PER_PAGE)
+        .offset(offset)
+        .select((CourseResource::as_select(), courses::course_name))
+        .load::<(CourseResource, String)>(conn)?
+        .into_iter()
+        .map(|(resource, course_name)| SearchResourceResult { resource, course_name })
+        .collect();
+    let total_resources = matching_resources_query(tsquery, query).select(count_star()).get_result::<i64>(conn)?;
+
+    let files = matching_files_query(tsquery, query)
+        .order((rank(FILE_DOCUMENT, tsquery).desc(), course_resources::dateuploaded.desc()))
+        .limit(SEARCH_RESULTS_PER_PAGE)
+        .offset(offset)
+        .select((CourseResourceFile::as_select(), course_resources::title, course_resources::resource_type, courses::course_id, courses::course_name))
+        .load::<(CourseResourceFile, String, _, String, String)>(conn)?
+        .into_iter()
+        .map(|(file, resource_title, resource_type, course_id, course_name)| SearchFileResult { file, resource_title, resource_type, course_id, course_name })
+        .collect();
+    let total_files = matching_files_query(tsquery, query).select(count_star()).get_result::<i64>(conn)?;
+
+    // Links don't have a resource type, so filtering by one leaves them out
+    let (links, total_links) = if query.resource_type.is_some() {
+        (Vec::new(), 0)
+    } else {
+        let links = matching_links_query(tsquery, query)
+            .order((rank(LINK_DOCUMENT, tsquery).desc(), course_resource_links::date_added.desc()))
+            .limit(SEARCH_RESULTS_PER_PAGE)
+            .offset(offset)
+            .select((CourseResourceLink::as_select(), courses::course_name))
+            .load::<(CourseResourceLink, String)>(conn)?
+            .into_iter()
+            .map(|(link, course_name)| SearchLinkResult { course_id: link.course_id.clone(), link: link.into(), course_name })
+            .collect();
+        (links, matching_links_query(tsquery, query).select(count_star()).get_result::<i64>(conn)?)
+    };
+
+    Ok(SearchResponse {
+        resources: SearchResultGroup { results: resources, total: total_resources },
+        files: SearchResultGroup { results: files, total: total_files },
+        links: SearchResultGroup { results: links, total: total_links }
+    })
+}
+
+pub async fn search(DbConn(mut conn): DbConn, Query(query): Query<SearchQuery>) -> Result<impl IntoResponse, StatusCode> {
+    let Some(tsquery) = prefix_tsquery(&query.q) else {
+        return Ok(error_response(StatusCode::BAD_REQUEST, "Search query can't be empty"));
+    };
+
+    match search_in_db(&mut conn, &tsquery, &query) {
+        Ok(response) => Ok(Json(response).into_response()),
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}
@@ -0,0 +1,57 @@
+//! The semester a resource is from, stored and sent as its name.
+//! Any casing is accepted when deserializing, so "summer" is read as Summer
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = Text)]
+#[serde(try_from = "String", into = "&'static str")]
+pub enum Semester {
+    First,
+    Second,
+    Summer
+}
+
+impl Semester {
+    pub const ALL: [Semester; 3] = [Semester::First, Semester::Second, Semester::Summer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Semester::First => "First",
+            Semester::Second => "Second",
+            Semester::Summer => "Summer"
+        }
+    }
+}
+
+impl From<Semester> for &'static str {
+    fn from(semester: Semester) -> Self {
+        semester.as_str()
+    }
+}
+
+impl TryFrom<String> for Semester {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Semester::ALL.into_iter()
+            .find(|semester| semester.as_str().eq_ignore_ascii_case(value.trim()))
+            .ok_or_else(|| format!("Invalid semester {} (Must be First, Second or Summer)", value))
+    }
+}
+
+impl ToSql<Text, Pg> for Semester {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), &mut out.reborrow())
+    }
+}
+
+impl FromSql<Text, Pg> for Semester {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        Ok(Semester::try_from(<String as FromSql<Text, Pg>>::from_sql(bytes)?)?)
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+
+use crate::accounts::TokenKeys;
+use crate::connection::DbPool;
+use crate::course_suggestions::CourseSuggestions;
+use crate::mail::Mailer;
+use crate::storage::StorageBackend;
+
+/// Shared state handed to every handler through axum's `State` extractor
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: DbPool,
+    pub storage: Arc<dyn StorageBackend>,
+    /// How many files of a single upload request can be uploaded to storage at the same time
+    pub upload_concurrency: usize,
+    pub mailer: Arc<dyn Mailer>,
+    pub tokens: Arc<TokenKeys>,
+    /// How many pending reports it takes to hide an item until a moderator looks at it
+    pub report_hide_threshold: i64,
+    pub course_suggestions: Arc<CourseSuggestions>,
+}
+
+impl FromRef<AppState> for DbPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
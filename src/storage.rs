@@ -0,0 +1,247 @@
+//! Storage backends for the files attached to course resources.
+//! Production uploads go to Google Cloud Storage, while local development and CI
+//! can use `LocalStorage` which keeps everything on disk and serves it through `/v1/files`.
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use reqwest::{Body, Client, Url};
+use tokio::io::AsyncWriteExt;
+
+use crate::authentication::get_token_cache;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Object {0} not found in storage")]
+    NotFound(String),
+    #[error("Invalid storage key: {0}")]
+    InvalidKey(String),
+    #[error("Storage request failed: {0}")]
+    Request(String),
+    #[error("Storage authentication failed: {0}")]
+    Auth(String),
+    #[error("Storage I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<reqwest::Error> for StorageError {
+    fn from(e: reqwest::Error) -> Self {
+        StorageError::Request(e.to_string())
+    }
+}
+
+/// Chunks of a file being uploaded, so uploads never have to be fully buffered in memory
+pub type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>;
+
+/// Somewhere to put the files of course resources.
+/// Keys are `/` separated paths such as `course_resources/CS116/<resource id>/midterm.pdf`.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Stores the object, failing (and not leaving a partial object behind) if `data` yields an error
+    async fn put(&self, key: &str, content_type: &str, data: ByteStream) -> Result<(), StorageError>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// Public URL that clients can download the object from
+    fn url(&self, key: &str) -> String;
+
+    /// Whether the files need to be served by this backend through `/v1/files/*key`
+    fn serves_files(&self) -> bool {
+        false
+    }
+}
+
+/// Picks the storage backend using the `STORAGE_BACKEND` environment variable (`gcs` by default, or `local`)
+pub async fn storage_backend_from_env() -> Arc<dyn StorageBackend> {
+    match dotenvy::var("STORAGE_BACKEND").unwrap_or_default().to_lowercase().as_str() {
+        "local" => {
+            let root = dotenvy::var("LOCAL_STORAGE_PATH").unwrap_or("./storage".to_string());
+            let base_url = dotenvy::var("LOCAL_STORAGE_BASE_URL").unwrap_or("http://localhost:9093/v1/files".to_string());
+            println!("Using local storage at {}", root);
+            Arc::new(LocalStorage::new(root, base_url))
+        }
+        _ => {
+            // Initialize token cache
+            if let Err(e) = get_token_cache().await {
+                eprintln!("Failed to initialize token cache: {}", e);
+            }
+
+            println!("Initialized token cache for buckets");
+            let bucket = dotenvy::var("GCS_BUCKET_NAME").unwrap_or("gjufilesresources".to_string());
+            Arc::new(GcsStorage::new(bucket))
+        }
+    }
+}
+
+pub struct GcsStorage {
+    bucket: String,
+    client: Client,
+}
+
+impl GcsStorage {
+    pub fn new(bucket: String) -> Self {
+        GcsStorage { bucket, client: Client::new() }
+    }
+
+    // Object names have to be a single path segment in the JSON API, so `/` is escaped too
+    fn object_url(&self, key: &str) -> Url {
+        let mut url = Url::parse("https://storage.googleapis.com/storage/v1/b").unwrap();
+        url.path_segments_mut().unwrap().push(&self.bucket).push("o").push(key);
+        url
+    }
+
+    async fn token() -> Result<String, StorageError> {
+        get_token_cache().await.map_err(|e| StorageError::Auth(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for GcsStorage {
+    async fn put(&self, key: &str, content_type: &str, data: ByteStream) -> Result<(), StorageError> {
+        let request_url = format!("https://storage.googleapis.com/upload/storage/v1/b/{}/o", self.bucket);
+        let response = self.client
+            .post(&request_url)
+            .query(&[("uploadType", "media"), ("name", key)])
+            .bearer_auth(Self::token().await?)
+            .header("Content-Type", content_type)
+            .body(Body::wrap_stream(data))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(StorageError::Request(format!("Uploading {} failed with status {}", key, response.status())));
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let response = self.client
+            .get(self.object_url(key))
+            .query(&[("alt", "media")])
+            .bearer_auth(Self::token().await?)
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(StorageError::NotFound(key.to_string()));
+        }
+
+        if !response.status().is_success() {
+            return Err(StorageError::Request(format!("Downloading {} failed with status {}", key, response.status())));
+        }
+
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let response = self.client
+            .delete(self.object_url(key))
+            .bearer_auth(Self::token().await?)
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(StorageError::NotFound(key.to_string()));
+        }
+
+        if !response.status().is_success() {
+            return Err(StorageError::Request(format!("Deleting {} failed with status {}", key, response.status())));
+        }
+
+        Ok(())
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("https://storage.googleapis.com/{}/{}", self.bucket, key)
+    }
+}
+
+pub struct LocalStorage {
+    root: PathBuf,
+    base_url: String,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>, base_url: String) -> Self {
+        LocalStorage { root: root.into(), base_url: base_url.trim_end_matches('/').to_string() }
+    }
+
+    // Keys come from (sanitized) user file names, so never let them escape the storage root
+    fn path_for_key(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let relative = Path::new(key);
+        let is_plain_path = relative.components().all(|c| matches!(c, Component::Normal(_)));
+        if key.is_empty() || !is_plain_path {
+            return Err(StorageError::InvalidKey(key.to_string()));
+        }
+
+        Ok(self.root.join(relative))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn put(&self, key: &str, _content_type: &str, mut data: ByteStream) -> Result<(), StorageError> {
+        let path = self.path_for_key(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut file = tokio::fs::File::create(&path).await?;
+        let written: Result<(), std::io::Error> = async {
+            while let Some(chunk) = data.next().await {
+                file.write_all(&chunk?).await?;
+            }
+            file.flush().await
+        }.await;
+
+        if let Err(e) = written {
+            _ = tokio::fs::remove_file(&path).await;
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        match tokio::fs::read(self.path_for_key(key)?).await {
+            Ok(data) => Ok(data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(StorageError::NotFound(key.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match tokio::fs::remove_file(self.path_for_key(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(StorageError::NotFound(key.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{}", self.base_url, key)
+    }
+
+    fn serves_files(&self) -> bool {
+        true
+    }
+}
+
+pub fn file_content_type(file_name: &str) -> &'static str {
+    let extension = file_name.rsplit('.').next().unwrap_or_default().to_lowercase();
+    match extension.as_str() {
+        "pdf" => "application/pdf",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "pptx" => "application/vnd.ms-powerpoint",
+        "ppt" => "application/vnd.ms-powerpoint",
+        "jpg" => "image/jpeg",
+        "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        _ => "application/octet-stream"
+    }
+}
@@ -0,0 +1,59 @@
+//! Up and down votes on course resources.
+//! The score of a resource is stored on it so sorting by it doesn't need to add up the votes every time.
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::accounts::AuthenticatedUser;
+use crate::connection::DbConn;
+use crate::models::{error_response, ResourceVote, VoteOnResource, VoteOnResourceResponse};
+use crate::schema::{course_resources, resource_votes};
+
+/// Sets (or with a vote of 0, removes) the user's vote and returns the resource's new score
+fn set_resource_vote_in_db(conn: &mut PgConnection, resource_id: Uuid, user_id: Uuid, vote: i16) -> Result<i32, diesel::result::Error> {
+    conn.transaction(|conn| {
+        // Lock the resource so concurrent votes can't overwrite each other's score
+        course_resources::table
+            .find(resource_id)
+            .filter(course_resources::is_hidden.eq(false))
+            .for_update()
+            .select(course_resources::resource_id)
+            .first::<Uuid>(conn)?;
+
+        let previous_vote = resource_votes::table
+            .find((resource_id, user_id))
+            .select(resource_votes::vote)
+            .first::<i16>(conn)
+            .optional()?
+            .unwrap_or(0);
+
+        if vote == 0 {
+            diesel::delete(resource_votes::table.find((resource_id, user_id))).execute(conn)?;
+        } else {
+            let new_vote = ResourceVote { resource_id, user_id, vote, created_at: chrono::Utc::now() };
+            diesel::insert_into(resource_votes::table)
+                .values(&new_vote)
+                .on_conflict((resource_votes::resource_id, resource_votes::user_id))
+                .do_update()
+                .set((resource_votes::vote.eq(vote), resource_votes::created_at.eq(new_vote.created_at)))
+                .execute(conn)?;
+        }
+
+        diesel::update(course_resources::table.find(resource_id))
+            .set(course_resources::score.eq(course_resources::score + i32::from(vote - previous_vote)))
+            .returning(course_resources::score)
+            .get_result::<i32>(conn)
+    })
+}
+
+pub async fn vote_on_course_resource(AuthenticatedUser(user): AuthenticatedUser, DbConn(mut conn): DbConn, Path(resource_id): Path<Uuid>, Json(payload): Json<VoteOnResource>) -> Result<impl IntoResponse, StatusCode> {
+    if !(-1..=1).contains(&payload.vote) {
+        return Ok(error_response(StatusCode::BAD_REQUEST, "Invalid vote (Must be 1 to upvote, -1 to downvote or 0 to remove your vote)"));
+    }
+
+    match set_resource_vote_in_db(&mut conn, resource_id, user.user_id, payload.vote) {
+        Ok(score) => Ok(Json(VoteOnResourceResponse { score, vote: payload.vote }).into_response()),
+        Err(diesel::result::Error::NotFound) => Ok(error_response(StatusCode::NOT_FOUND, format!("Resource with id {} not found", resource_id))),
+        Err(e) => Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    }
+}